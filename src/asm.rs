@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use memory::MemoryLayout;
+
+// Something an instruction works on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Register(u16),
+    Index,
+    // [I], the memory I points at
+    Memory,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    // SCHIP's big digits
+    BigFont,
+    Bcd,
+    // a number, or the address of a label
+    Value(u32)
+}
+
+// A line with something to assemble on it, once comments and labels are gone
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>
+}
+
+// Turns source in the syntax `disasm` writes back into a program:
+//
+//   loop:   LD V0, 0x0A      ; comments start with a semicolon
+//           CALL draw
+//           JP loop
+//   draw:   DRW V0, V1, 5
+//           RET
+//   sprite: DB 0xF0, 0x90, 0b11110000
+//
+// Labels stand in for the address of whatever follows them, counting
+// from the layout's load address, where the program will be loaded.
+pub fn assemble(source: &str, layout: &MemoryLayout) -> Result<Vec<u8>, String> {
+    let address = layout.load_address;
+    // first find where every label ends up...
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut next_address = address as u32;
+    for (number, line) in source.lines().enumerate() {
+        let mut line = line.split(';').next().unwrap().trim();
+        if let Some(colon) = line.find(':') {
+            let label = line[..colon].trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(format!("line {}: '{}' is not a label", number + 1, label));
+            }
+            if labels.insert(label.to_lowercase(), next_address).is_some() {
+                return Err(format!("line {}: label '{}' is already used", number + 1, label));
+            }
+            line = line[colon + 1..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let mnemonic = parts.next().unwrap().to_uppercase();
+        let operands: Vec<&str> = match parts.next() {
+            Some(operands) => operands.split(',').map(|operand| operand.trim()).collect(),
+            None => Vec::new()
+        };
+        next_address += match mnemonic.as_str() {
+            "DB" => operands.len() as u32,
+            "DW" => operands.len() as u32 * 2,
+            _ => 2
+        };
+        statements.push(Statement { line: number + 1, mnemonic, operands });
+    }
+
+    // ...then put it all together
+    let mut program = Vec::new();
+    for statement in statements.iter() {
+        let operands = statement.operands.iter()
+            .map(|operand| parse_operand(operand, &labels))
+            .collect::<Result<Vec<Operand>, String>>()
+            .and_then(|operands| encode(&statement.mnemonic, &operands, &mut program));
+        operands.map_err(|message| format!("line {}: {}", statement.line, message))?;
+    }
+
+    if program.len() > layout.program_room() {
+        return Err(format!("{} bytes won't fit in memory from 0x{:03X}", program.len(), address));
+    }
+    Ok(program)
+}
+
+fn parse_operand(text: &str, labels: &HashMap<String, u32>) -> Result<Operand, String> {
+    let upper = text.to_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::Index,
+        "[I]" => Operand::Memory,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::BigFont,
+        "B" => Operand::Bcd,
+        _ if upper.len() == 2 && upper.starts_with('V') => match u16::from_str_radix(&upper[1..], 16) {
+            Ok(register) => Operand::Register(register),
+            Err(_) => return Err(format!("'{}' is not a register", text))
+        },
+        _ => match parse_number(text) {
+            Some(value) => Operand::Value(value),
+            None => match labels.get(&text.to_lowercase()) {
+                Some(&address) => Operand::Value(address),
+                None => return Err(format!("'{}' is not a number or a label", text))
+            }
+        }
+    };
+    Ok(operand)
+}
+
+// 0x hex, 0b binary or decimal
+pub fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse::<u32>().ok()
+    }
+}
+
+fn fits(value: u32, max: u32, what: &str) -> Result<u16, String> {
+    if value > max {
+        return Err(format!("0x{:X} is too big for {}", value, what));
+    }
+    Ok(value as u16)
+}
+
+fn address(value: u32) -> Result<u16, String> {
+    fits(value, 0xfff, "an address")
+}
+
+fn byte(value: u32) -> Result<u16, String> {
+    fits(value, 0xff, "a byte")
+}
+
+fn encode(mnemonic: &str, operands: &[Operand], program: &mut Vec<u8>) -> Result<(), String> {
+    use self::Operand::*;
+
+    // data goes in as it is
+    match mnemonic {
+        "DB" => {
+            for operand in operands.iter() {
+                match *operand {
+                    Value(value) => program.push(byte(value)? as u8),
+                    _ => return Err("DB takes bytes".to_string())
+                }
+            }
+            return Ok(());
+        },
+        "DW" => {
+            for operand in operands.iter() {
+                match *operand {
+                    Value(value) => program.extend_from_slice(&fits(value, 0xffff, "a word")?.to_be_bytes()),
+                    _ => return Err("DW takes words".to_string())
+                }
+            }
+            return Ok(());
+        },
+        _ => {}
+    }
+
+    let word = match (mnemonic, operands) {
+        ("CLS", []) => 0x00e0,
+        ("RET", []) => 0x00ee,
+        ("SYS", [Value(a)]) => address(*a)?,
+        ("JP", [Value(a)]) => 0x1000 | address(*a)?,
+        ("JP", [Register(0), Value(a)]) => 0xb000 | address(*a)?,
+        ("CALL", [Value(a)]) => 0x2000 | address(*a)?,
+        ("SE", [Register(x), Value(b)]) => 0x3000 | x << 8 | byte(*b)?,
+        ("SNE", [Register(x), Value(b)]) => 0x4000 | x << 8 | byte(*b)?,
+        ("SE", [Register(x), Register(y)]) => 0x5000 | x << 8 | y << 4,
+        ("LD", [Register(x), Value(b)]) => 0x6000 | x << 8 | byte(*b)?,
+        ("ADD", [Register(x), Value(b)]) => 0x7000 | x << 8 | byte(*b)?,
+        ("LD", [Register(x), Register(y)]) => 0x8000 | x << 8 | y << 4,
+        ("OR", [Register(x), Register(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [Register(x), Register(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [Register(x), Register(y)]) => 0x8003 | x << 8 | y << 4,
+        ("ADD", [Register(x), Register(y)]) => 0x8004 | x << 8 | y << 4,
+        ("SUB", [Register(x), Register(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [Register(x), Register(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SHR", [Register(x)]) => 0x8006 | x << 8 | x << 4,
+        ("SUBN", [Register(x), Register(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [Register(x), Register(y)]) => 0x800e | x << 8 | y << 4,
+        ("SHL", [Register(x)]) => 0x800e | x << 8 | x << 4,
+        ("SNE", [Register(x), Register(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [Index, Value(a)]) => 0xa000 | address(*a)?,
+        ("RND", [Register(x), Value(b)]) => 0xc000 | x << 8 | byte(*b)?,
+        ("DRW", [Register(x), Register(y), Value(n)]) => 0xd000 | x << 8 | y << 4 | fits(*n, 0xf, "a sprite height")?,
+        ("SKP", [Register(x)]) => 0xe09e | x << 8,
+        ("SKNP", [Register(x)]) => 0xe0a1 | x << 8,
+        ("LD", [Register(x), DelayTimer]) => 0xf007 | x << 8,
+        ("LD", [Register(x), Key]) => 0xf00a | x << 8,
+        ("LD", [DelayTimer, Register(x)]) => 0xf015 | x << 8,
+        ("LD", [SoundTimer, Register(x)]) => 0xf018 | x << 8,
+        ("ADD", [Index, Register(x)]) => 0xf01e | x << 8,
+        ("LD", [Font, Register(x)]) => 0xf029 | x << 8,
+        ("LD", [BigFont, Register(x)]) => 0xf030 | x << 8,
+        ("LD", [Bcd, Register(x)]) => 0xf033 | x << 8,
+        ("LD", [Memory, Register(x)]) => 0xf055 | x << 8,
+        ("LD", [Register(x), Memory]) => 0xf065 | x << 8,
+        _ => return Err(format!("can't assemble {} with those operands", mnemonic))
+    };
+    program.extend_from_slice(&word.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod asm_tests {
+    use super::*;
+    use disasm::disassemble;
+    use std::fs;
+
+    #[test]
+    fn labels_and_data_assemble() {
+        let layout = MemoryLayout::default();
+        let source = "start:  LD V0, 10   ; a comment\n\
+                      \n\
+                      \tCALL draw\n\
+                      \tJP start\n\
+                      draw: drw v0, v1, 5\n\
+                      \tRET\n\
+                      sprite: DB 0xF0, 0b10010000\n\
+                      \tLD I, sprite\n";
+        assert_eq!(assemble(source, &layout).unwrap(), vec![
+            0x60, 0x0a, 0x22, 0x06, 0x12, 0x00, 0xd0, 0x15, 0x00, 0xee, 0xf0, 0x90, 0xa2, 0x0a
+        ]);
+        assert_eq!(assemble("LD HF, V3", &layout).unwrap(), vec![0xf3, 0x30]);
+    }
+
+    #[test]
+    fn mistakes_are_reported_by_line() {
+        let layout = MemoryLayout::default();
+        assert!(assemble("CLS\nLD V0, 0x100", &layout).unwrap_err().starts_with("line 2: 0x100 is too big"));
+        assert!(assemble("JP nowhere", &layout).unwrap_err().contains("'nowhere' is not a number or a label"));
+        assert!(assemble("DRW V0, 3, 4", &layout).unwrap_err().contains("can't assemble DRW"));
+        assert!(assemble("a:\na: CLS", &layout).unwrap_err().contains("already used"));
+    }
+
+    #[test]
+    fn disassembled_games_assemble_back() {
+        let layout = MemoryLayout::default();
+        for name in ["PONG", "BLINKY", "MAZE"].iter() {
+            let program = fs::read(format!("roms/c8games/{}", name)).unwrap();
+            assert_eq!(assemble(&disassemble(&program, 0x200), &layout).unwrap(), program, "{}", name);
+        }
+    }
+
+    #[test]
+    fn programs_must_fit_in_memory() {
+        let source = "DB 0\n".repeat(4000);
+        assert!(assemble(&source, &MemoryLayout::default()).unwrap_err().contains("4000 bytes won't fit"));
+        let big = MemoryLayout::new(0x200, 0x000, 65536).unwrap();
+        assert_eq!(assemble(&source, &big).unwrap().len(), 4000);
+    }
+}
@@ -0,0 +1,287 @@
+use sdl2::audio::AudioCallback;
+use std::f32::consts::PI;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sine,
+    Noise
+}
+
+impl Waveform {
+    pub fn parse(text: &str) -> Result<Waveform, String> {
+        match text.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sine" => Ok(Waveform::Sine),
+            "noise" => Ok(Waveform::Noise),
+            _ => Err(format!("Unknown waveform '{}', try square, triangle, sine or noise", text))
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Sine => "sine",
+            Waveform::Noise => "noise"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What the beeper sounds like
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tone {
+    // Hz. For noise, how often a new random level is picked.
+    pub frequency: f32,
+    pub waveform: Waveform,
+    // 0.0 to 1.0
+    pub volume: f32
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            // 220hz is a pleasant atari-esque beep
+            frequency: 220.0,
+            waveform: Waveform::Square,
+            volume: 0.25
+        }
+    }
+}
+
+// Fading in and out over a few milliseconds, rather than starting and
+// stopping mid-wave, is what gets rid of the click
+const ATTACK_MS: f32 = 5.0;
+const RELEASE_MS: f32 = 10.0;
+
+// The CHIP-8 sound generator: a single tone, played for as long as the
+// sound timer runs. The length is counted in samples rather than checked
+// once per frame, so it comes out exact whatever the frame timing does.
+pub struct Beeper {
+    tone: Tone,
+    sample_rate: i32,
+    // How far through one cycle of the wave we are, 0.0 to 1.0
+    phase: f32,
+    phase_inc: f32,
+    // Current loudness, ramped towards 1.0 or 0.0 depending on the gate
+    envelope: f32,
+    attack_step: f32,
+    release_step: f32,
+    // Samples left before the tone stops
+    remaining: u64,
+    muted: bool,
+    // xorshift state for the noise waveform, and the level it's holding
+    noise_state: u32,
+    noise_level: f32
+}
+
+impl Beeper {
+    pub fn new(tone: Tone, sample_rate: i32) -> Beeper {
+        let samples_per_ms = sample_rate as f32 / 1000.0;
+        Beeper {
+            phase_inc: tone.frequency / sample_rate as f32,
+            tone,
+            sample_rate,
+            phase: 0.0,
+            envelope: 0.0,
+            attack_step: 1.0 / (ATTACK_MS * samples_per_ms),
+            release_step: 1.0 / (RELEASE_MS * samples_per_ms),
+            remaining: 0,
+            muted: false,
+            noise_state: 0x1234_5678,
+            noise_level: 1.0
+        }
+    }
+
+    // Sound for exactly this many 60hz timer ticks of emulated time, replacing
+    // whatever was playing. When emulating at double speed, the same number
+    // of ticks takes half as long.
+    pub fn play_ticks(&mut self, ticks: u8, speed: f32) {
+        let seconds = ticks as f64 / 60.0 / speed as f64;
+        self.remaining = (seconds * self.sample_rate as f64).round() as u64;
+    }
+
+    // Takes effect straight away, even in the middle of a beep
+    pub fn tone(&self) -> &Tone {
+        &self.tone
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.phase_inc = tone.frequency / self.sample_rate as f32;
+        self.tone = tone;
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    fn wave(&mut self) -> f32 {
+        match self.tone.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Sine => (2.0 * PI * self.phase).sin(),
+            Waveform::Noise => self.noise_level
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let gate = self.remaining > 0;
+        if gate {
+            self.remaining -= 1;
+        }
+
+        let target = if gate && !self.muted { 1.0 } else { 0.0 };
+        if self.envelope < target {
+            self.envelope = (self.envelope + self.attack_step).min(target);
+        } else if self.envelope > target {
+            self.envelope = (self.envelope - self.release_step).max(target);
+        }
+
+        let sample = self.wave() * self.tone.volume * self.envelope;
+
+        self.phase += self.phase_inc;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+
+            // xorshift32, a new random level once per cycle
+            self.noise_state ^= self.noise_state << 13;
+            self.noise_state ^= self.noise_state >> 17;
+            self.noise_state ^= self.noise_state << 5;
+            self.noise_level = (self.noise_state as f32 / u32::MAX as f32) * 2.0 - 1.0;
+        }
+
+        sample
+    }
+}
+
+impl AudioCallback for Beeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = self.next_sample();
+        }
+    }
+}
+
+#[cfg(test)]
+mod audio_tests {
+    use super::*;
+
+    fn test_beeper(waveform: Waveform) -> Beeper {
+        // one cycle every 8 samples
+        let tone = Tone { frequency: 1000.0, waveform, volume: 1.0 };
+        let mut beeper = Beeper::new(tone, 8000);
+        beeper.envelope = 1.0;
+        beeper.remaining = u64::MAX;
+        beeper
+    }
+
+    fn one_cycle(beeper: &mut Beeper) -> Vec<f32> {
+        (0..8).map(|_| beeper.next_sample()).collect()
+    }
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(Waveform::parse("sine"), Ok(Waveform::Sine));
+        assert_eq!(Waveform::parse("Noise"), Ok(Waveform::Noise));
+        assert!(Waveform::parse("sawtooth").is_err());
+    }
+
+    #[test]
+    fn square_wave_works() {
+        let samples = one_cycle(&mut test_beeper(Waveform::Square));
+        assert_eq!(samples, vec![1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+    }
+
+    #[test]
+    fn triangle_wave_works() {
+        let samples = one_cycle(&mut test_beeper(Waveform::Triangle));
+        assert_eq!(samples, vec![1.0, 0.5, 0.0, -0.5, -1.0, -0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn sine_wave_works() {
+        let samples = one_cycle(&mut test_beeper(Waveform::Sine));
+        assert!(samples[0].abs() < 0.001);
+        assert!((samples[2] - 1.0).abs() < 0.001);
+        assert!((samples[6] + 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn noise_stays_in_range() {
+        let mut beeper = test_beeper(Waveform::Noise);
+        for _ in 0..1000 {
+            let sample = beeper.next_sample();
+            assert!((-1.0..=1.0).contains(&sample));
+        }
+    }
+
+    #[test]
+    fn gate_ramps_instead_of_clicking() {
+        let mut beeper = Beeper::new(Tone { waveform: Waveform::Square, ..Tone::default() }, 44_100);
+        assert_eq!(beeper.next_sample(), 0.0); // silent until told to play
+
+        beeper.play_ticks(60, 1.0);
+        let first = beeper.next_sample();
+        assert!(first > 0.0 && first < 0.01);
+
+        for _ in 0..1000 {
+            beeper.next_sample();
+        }
+        assert_eq!(beeper.envelope, 1.0);
+
+        // stopping fades out rather than cutting off
+        beeper.play_ticks(0, 1.0);
+        beeper.next_sample();
+        assert!(beeper.envelope > 0.9);
+        for _ in 0..1000 {
+            beeper.next_sample();
+        }
+        assert_eq!(beeper.envelope, 0.0);
+    }
+
+    #[test]
+    fn ticks_play_for_an_exact_number_of_samples() {
+        // 10 samples per tick
+        let mut beeper = Beeper::new(Tone::default(), 600);
+        beeper.play_ticks(6, 1.0);
+        for _ in 0..59 {
+            beeper.next_sample();
+        }
+        assert_eq!(beeper.remaining, 1);
+        beeper.next_sample();
+        assert_eq!(beeper.remaining, 0);
+    }
+
+    #[test]
+    fn ticks_follow_emulation_speed() {
+        let mut beeper = Beeper::new(Tone::default(), 600);
+
+        beeper.play_ticks(6, 2.0); // fast forward
+        assert_eq!(beeper.remaining, 30);
+
+        beeper.play_ticks(6, 0.5); // slow motion
+        assert_eq!(beeper.remaining, 120);
+    }
+
+    #[test]
+    fn muted_beeper_is_silent() {
+        let mut beeper = test_beeper(Waveform::Square);
+        beeper.set_muted(true);
+        for _ in 0..1000 {
+            beeper.next_sample();
+        }
+        assert_eq!(beeper.next_sample(), 0.0);
+    }
+}
@@ -0,0 +1,259 @@
+use sdl2::keyboard::Keycode;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use std::fs;
+use std::path::{Path, PathBuf};
+use osd::{draw_box, draw_text, text_size, Anchor};
+use memory::MemoryLayout;
+use palette::Palette;
+use romdb::{identify, RomInfo};
+use ComputerState;
+
+// How many ROMs are listed at once
+const VISIBLE_ROMS: usize = 24;
+
+// Each CHIP-8 pixel in the preview is a square this big
+const PREVIEW_SCALE: u32 = 5;
+// The preview runs at a typical speed rather than the user's setting,
+// so every game gets going quickly
+const PREVIEW_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+const MARGIN: i32 = 16;
+
+struct RomEntry {
+    path: PathBuf,
+    // the file name
+    name: String,
+    // in bytes
+    size: u64,
+    // what the ROM database knows about it
+    rom: Option<&'static RomInfo>
+}
+
+impl RomEntry {
+    fn title(&self) -> &str {
+        self.rom.map(|rom| rom.title).unwrap_or(&self.name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BrowserAction {
+    Nothing,
+    Close,
+    Launch(PathBuf)
+}
+
+// A list of the ROMs in a directory to pick from, with the selected one
+// running in a small preview beside it
+pub struct Browser {
+    dir: PathBuf,
+    roms: Vec<RomEntry>,
+    selected: usize,
+    preview: Option<ComputerState>,
+    // The machine ROMs are previewed in, and have to fit
+    layout: MemoryLayout
+}
+
+impl Browser {
+    pub fn new(dir: &Path, layout: &MemoryLayout) -> Browser {
+        let mut browser = Browser {
+            dir: dir.to_path_buf(),
+            roms: list_roms(dir, layout.program_room() as u64),
+            selected: 0,
+            preview: None,
+            layout: layout.clone()
+        };
+        browser.start_preview();
+        browser
+    }
+
+    fn start_preview(&mut self) {
+        self.preview = self.roms.get(self.selected).and_then(|rom| {
+            let program = fs::read(&rom.path).ok()?;
+            let mut preview = ComputerState::with_layout(self.layout.clone());
+            preview.load_bytes(&program).ok()?;
+            Some(preview)
+        });
+    }
+
+    fn select(&mut self, index: usize) {
+        if index != self.selected {
+            self.selected = index;
+            self.start_preview();
+        }
+    }
+
+    pub fn key_down(&mut self, key: Keycode) -> BrowserAction {
+        let count = self.roms.len();
+        if count == 0 {
+            return match key {
+                Keycode::Escape | Keycode::Backspace => BrowserAction::Close,
+                _ => BrowserAction::Nothing
+            };
+        }
+
+        match key {
+            Keycode::Up => self.select((self.selected + count - 1) % count),
+            Keycode::Down => self.select((self.selected + 1) % count),
+            Keycode::PageUp => self.select(self.selected.saturating_sub(VISIBLE_ROMS)),
+            Keycode::PageDown => self.select((self.selected + VISIBLE_ROMS).min(count - 1)),
+            Keycode::Home => self.select(0),
+            Keycode::End => self.select(count - 1),
+            Keycode::Return | Keycode::KpEnter | Keycode::Space => {
+                return BrowserAction::Launch(self.roms[self.selected].path.clone());
+            },
+            Keycode::Escape | Keycode::Backspace => return BrowserAction::Close,
+            _ => {}
+        }
+        BrowserAction::Nothing
+    }
+
+    // Call once per frame, to keep the preview running
+    pub fn tick(&mut self) {
+        if let Some(ref mut preview) = self.preview {
+            for _ in 0..PREVIEW_INSTRUCTIONS_PER_FRAME {
+                if preview.is_blocked() {
+                    break;
+                }
+                preview.step();
+            }
+            preview.vblank();
+        }
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, palette: &Palette, area: Rect) {
+        canvas.set_draw_color(palette.background);
+        canvas.fill_rect(area).unwrap();
+
+        let heading = vec![format!("Choose a game from {}", self.dir.display())];
+        draw_text(canvas, &heading, area.x() + MARGIN, area.y() + MARGIN, palette.foreground);
+        let (_, heading_height) = text_size(&heading);
+        let top = area.y() + MARGIN * 2 + heading_height as i32;
+
+        let mut lines = Vec::new();
+        if self.roms.is_empty() {
+            lines.push("No ROMs here".to_string());
+        }
+        // long lists scroll to keep the selection in view
+        let first = (self.selected + 1).saturating_sub(VISIBLE_ROMS);
+        for (index, rom) in self.roms.iter().enumerate().skip(first).take(VISIBLE_ROMS) {
+            let pointer = if index == self.selected { ">" } else { " " };
+            lines.push(format!("{} {:<16}{:>5} bytes", pointer, rom.title(), rom.size));
+        }
+        let list_area = Rect::new(area.x(), top - MARGIN, area.width(), area.height());
+        draw_box(canvas, palette, list_area, Anchor::TopLeft, &lines);
+
+        // the preview sits on the right, at the top of the list
+        let preview_width = 64 * PREVIEW_SCALE;
+        let preview_height = 32 * PREVIEW_SCALE;
+        let preview_x = area.right() - MARGIN - preview_width as i32;
+        canvas.set_draw_color(palette.shade(0.2));
+        canvas.fill_rect(Rect::new(preview_x - 4, top - 4, preview_width + 8, preview_height + 8)).unwrap();
+        canvas.set_draw_color(palette.background);
+        canvas.fill_rect(Rect::new(preview_x, top, preview_width, preview_height)).unwrap();
+
+        if let Some(ref preview) = self.preview {
+            let pixels: Vec<Rect> = preview.gfx.iter().enumerate()
+                .filter(|&(_, pixel)| *pixel != 0)
+                .map(|(index, _)| Rect::new(
+                    preview_x + ((index % 64) as u32 * PREVIEW_SCALE) as i32,
+                    top + ((index / 64) as u32 * PREVIEW_SCALE) as i32,
+                    PREVIEW_SCALE, PREVIEW_SCALE))
+                .collect();
+            canvas.set_draw_color(palette.foreground);
+            canvas.fill_rects(&pixels).unwrap();
+        }
+
+        if let Some(rom) = self.roms.get(self.selected) {
+            let mut details = vec![rom.title().to_string()];
+            if let Some(known) = rom.rom {
+                details.push(format!("by {}, for {}", known.author, known.platform));
+            }
+            details.push(format!("{} bytes", rom.size));
+            details.push(rom.path.display().to_string());
+            draw_text(canvas, &details, preview_x, top + preview_height as i32 + MARGIN, palette.foreground);
+        }
+
+        let help = vec!["Up/Down to choose, Enter to play, Escape to go back".to_string()];
+        let (_, help_height) = text_size(&help);
+        draw_text(canvas, &help, area.x() + MARGIN, area.bottom() - MARGIN - help_height as i32, palette.shade(0.6));
+    }
+}
+
+// Files in a directory that look like CHIP-8 programs, by name, and
+// are no bigger than `room`
+fn list_roms(dir: &Path, room: u64) -> Vec<RomEntry> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+
+    let mut roms: Vec<RomEntry> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let size = entry.metadata().ok().filter(|m| m.is_file()).map(|m| m.len())?;
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let extension = path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+            // skip hidden files, and the files we keep next to ROMs
+            if size == 0 || size > room || name.starts_with('.') || extension == "pad" || extension == "state" {
+                return None;
+            }
+            let rom = fs::read(&path).ok().and_then(|program| identify(&program));
+            Some(RomEntry { path, name, size, rom })
+        })
+        .collect();
+    roms.sort_by(|a, b| a.path.cmp(&b.path));
+    roms
+}
+
+#[cfg(test)]
+mod browser_tests {
+    use super::*;
+
+    #[test]
+    fn list_roms_finds_the_bundled_games() {
+        let roms = list_roms(Path::new("roms/c8games"), 0xe00);
+        assert_eq!(roms.len(), 23);
+        assert_eq!(roms[0].name, "15PUZZLE");
+
+        let pong = roms.iter().find(|rom| rom.name == "PONG").unwrap();
+        assert_eq!(pong.size, 246);
+        assert_eq!(pong.title(), "Pong");
+        assert_eq!(pong.path, PathBuf::from("roms/c8games/PONG"));
+
+        assert!(list_roms(Path::new("no/such/dir"), 0xe00).is_empty());
+        // and ones too big for the machine are left out
+        assert!(list_roms(Path::new("roms/c8games"), 245).iter().all(|rom| rom.name != "PONG"));
+    }
+
+    #[test]
+    fn keys_choose_and_launch() {
+        let mut browser = Browser::new(Path::new("roms/c8games"), &MemoryLayout::default());
+        browser.key_down(Keycode::Up);
+        assert_eq!(browser.roms[browser.selected].name, "WIPEOFF");
+        browser.key_down(Keycode::Down);
+        browser.key_down(Keycode::Down);
+        assert_eq!(browser.key_down(Keycode::Return), BrowserAction::Launch(PathBuf::from("roms/c8games/BLINKY")));
+        assert_eq!(browser.key_down(Keycode::Escape), BrowserAction::Close);
+    }
+
+    #[test]
+    fn preview_runs_the_selected_rom() {
+        let mut browser = Browser::new(Path::new("roms/c8games"), &MemoryLayout::default());
+        let maze = browser.roms.iter().position(|rom| rom.name == "MAZE").unwrap();
+        browser.select(maze);
+        for _ in 0..60 {
+            browser.tick();
+        }
+        assert!(browser.preview.as_ref().unwrap().gfx.iter().any(|pixel| *pixel != 0));
+    }
+
+    #[test]
+    fn empty_directories_can_be_left() {
+        let mut browser = Browser::new(Path::new("no/such/dir"), &MemoryLayout::default());
+        assert!(browser.preview.is_none());
+        assert_eq!(browser.key_down(Keycode::Return), BrowserAction::Nothing);
+        assert_eq!(browser.key_down(Keycode::Escape), BrowserAction::Close);
+    }
+}
@@ -0,0 +1,332 @@
+use sdl2::pixels::Color;
+use std::fmt;
+use asm::parse_number;
+use audio::Waveform;
+use filters::Filter;
+use loader::is_stdin;
+use memory::{parse_memory_size, StackDepth};
+use palette::parse_color;
+use persistence::PersistenceMode;
+use quirks::Quirks;
+use watch::WatchMode;
+
+pub const USAGE: &str = "usage: chip8 [COMMAND] [OPTIONS] [FILE]
+
+Commands:
+  run        play a ROM, or pick one in the ROM browser (the default)
+  disasm     list the instructions in a ROM
+  asm        assemble a source file into a ROM
+  info       describe a ROM
+  trace      run a ROM without a window, printing every instruction
+  test       run a ROM without a window and print the screen it ends on
+  help       show this
+
+Display:
+  --scale N                 window pixels for each CHIP-8 pixel
+  --palette NAME            colour theme (--theme works too)
+  --fg RRGGBB, --bg RRGGBB  colours of your own
+  --persistence MODE        off, blend[:N] or phosphor[:DECAY]
+  --filter NAME             none, scanlines, grid, scale2x or crt
+  --keypad                  show a clickable keypad
+
+Emulation:
+  --speed N                 instructions per frame (--ipf works too)
+  --quirks PRESET           vip, schip or modern
+  --vblank                  drawing waits for the next frame
+  --key-wait-press          FX0A finishes when a key goes down
+  --seed N                  the same random numbers on every run
+  --start ADDR              start running at ADDR instead of the load address
+
+Memory:
+  --load ADDR               where programs go, 0x200 unless e.g. 0x600 for ETI-660
+  --font-at ADDR            where the font goes, 0x000 unless e.g. 0x050
+  --memory SIZE             how much there is, e.g. 2K, 4K (the default) or 64K
+  --vip-memory              keep the stack at 0xEA0 and the display at 0xF00, as the VIP did
+  --stack N|unlimited       calls deep before the machine stops, 16 unless --vip-memory
+  --font NAME|FILE          digit shapes: vip, chip48 (the default), schip, octo or a file
+
+Input:
+  --keys PRESET|FILE        key layout: qwerty, keypad, hex or a file
+  --pad FILE                game controller mapping
+
+Sound:
+  --tone HZ, --waveform NAME, --volume LEVEL
+
+Recording:
+  --wav FILE, --gif FILE    record the sound or the display from the start
+
+Other:
+  --roms DIR                where the ROM browser looks
+  --watch reset|restart     reload the ROM whenever it changes
+  --headless                run without a window, e.g. to record a GIF
+  --frames N                how long --headless, trace and test run for
+  --expect FILE             for test, the screen to compare with
+  -o, --output FILE         where disasm and asm write to";
+
+// What to do with the file given
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Run,
+    Disasm,
+    Asm,
+    Info,
+    Trace,
+    Test
+}
+
+impl Command {
+    fn parse(name: &str) -> Option<Command> {
+        match name {
+            "run" => Some(Command::Run),
+            "disasm" => Some(Command::Disasm),
+            "asm" => Some(Command::Asm),
+            "info" => Some(Command::Info),
+            "trace" => Some(Command::Trace),
+            "test" => Some(Command::Test),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Command::Run => "run",
+            Command::Disasm => "disasm",
+            Command::Asm => "asm",
+            Command::Info => "info",
+            Command::Trace => "trace",
+            Command::Test => "test"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Everything given on the command line. Anything left out is None (or
+// false), so saved settings and defaults can fill in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub help: bool,
+    // The ROM, or the source for asm
+    pub file: Option<String>,
+    pub output: Option<String>,
+
+    pub scale: Option<u32>,
+    pub theme: Option<String>,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub persistence: Option<PersistenceMode>,
+    pub filter: Option<Filter>,
+    pub keypad: bool,
+
+    pub instructions_per_frame: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub display_wait: bool,
+    pub key_wait_on_press: bool,
+    pub seed: Option<u64>,
+    pub start: Option<u16>,
+
+    pub load_address: Option<u16>,
+    pub font_address: Option<u16>,
+    pub memory_size: Option<usize>,
+    pub vip_memory: bool,
+    pub stack_depth: Option<StackDepth>,
+    pub font: Option<String>,
+
+    pub keys: Option<String>,
+    pub pad: Option<String>,
+
+    pub frequency: Option<f32>,
+    pub waveform: Option<Waveform>,
+    pub volume: Option<f32>,
+
+    pub wav: Option<String>,
+    pub gif: Option<String>,
+
+    pub roms: Option<String>,
+    pub watch: Option<WatchMode>,
+    pub headless: bool,
+    pub frames: Option<u32>,
+    pub expect: Option<String>
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, option: &str, what: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs {}", option, what))
+}
+
+// Anything 16 bits can reach; whether there's memory there depends on --memory
+fn address(text: &str) -> Result<u16, String> {
+    match parse_number(text) {
+        Some(address) if address <= 0xffff => Ok(address as u16),
+        _ => Err(format!("'{}' is not an address, e.g. 0x2A0", text))
+    }
+}
+
+fn count(text: &str, what: &str) -> Result<u32, String> {
+    match text.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("'{}' is not a number of {}", text, what))
+    }
+}
+
+// The command comes first, if there is one, then options and the file in
+// any order
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter().peekable();
+
+    if let Some(first) = args.peek().cloned() {
+        if first == "help" {
+            options.help = true;
+            args.next();
+        } else if let Some(command) = Command::parse(&first) {
+            options.command = command;
+            args.next();
+        }
+    }
+
+    while let Some(arg) = args.next() {
+        let args = &mut args;
+        match arg.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-o" | "--output" => options.output = Some(value(args, &arg, "a file")?),
+            "--scale" => {
+                let text = value(args, &arg, "a number")?;
+                options.scale = match text.parse::<u32>() {
+                    Ok(scale) if scale > 0 && scale <= 32 => Some(scale),
+                    _ => return Err(format!("'{}' is not a scale from 1 to 32", text))
+                };
+            },
+            "--palette" | "--theme" => options.theme = Some(value(args, &arg, "a name")?),
+            "--fg" => options.foreground = Some(parse_color(&value(args, &arg, "a colour")?)?),
+            "--bg" => options.background = Some(parse_color(&value(args, &arg, "a colour")?)?),
+            "--persistence" => options.persistence = Some(PersistenceMode::parse(&value(args, &arg, "a mode")?)?),
+            "--filter" => options.filter = Some(Filter::parse(&value(args, &arg, "a name")?)?),
+            "--keypad" => options.keypad = true,
+            "--speed" | "--ipf" => {
+                let text = value(args, &arg, "a number")?;
+                options.instructions_per_frame = Some(count(&text, "instructions per frame")?);
+            },
+            "--quirks" => options.quirks = Some(Quirks::preset(&value(args, &arg, "a preset")?)?),
+            "--vblank" => options.display_wait = true,
+            "--key-wait-press" => options.key_wait_on_press = true,
+            "--seed" => {
+                let text = value(args, &arg, "a number")?;
+                options.seed = Some(text.parse::<u64>().map_err(|_| format!("'{}' is not a seed", text))?);
+            },
+            "--start" => options.start = Some(address(&value(args, &arg, "an address")?)?),
+            "--load" => options.load_address = Some(address(&value(args, &arg, "an address")?)?),
+            "--font-at" => options.font_address = Some(address(&value(args, &arg, "an address")?)?),
+            "--memory" => options.memory_size = Some(parse_memory_size(&value(args, &arg, "a size")?)?),
+            "--vip-memory" => options.vip_memory = true,
+            "--stack" => options.stack_depth = Some(StackDepth::parse(&value(args, &arg, "a depth")?)?),
+            "--font" => options.font = Some(value(args, &arg, "a font or a file")?),
+            "--keys" => options.keys = Some(value(args, &arg, "a preset or a file")?),
+            "--pad" => options.pad = Some(value(args, &arg, "a file")?),
+            "--tone" => {
+                let text = value(args, &arg, "a frequency")?;
+                options.frequency = match text.parse::<f32>() {
+                    Ok(frequency) if frequency > 0.0 && frequency < 20_000.0 => Some(frequency),
+                    _ => return Err(format!("'{}' is not a frequency in Hz", text))
+                };
+            },
+            "--waveform" => options.waveform = Some(Waveform::parse(&value(args, &arg, "a name")?)?),
+            "--volume" => {
+                let text = value(args, &arg, "a level")?;
+                options.volume = match text.parse::<f32>() {
+                    Ok(volume) if (0.0..=1.0).contains(&volume) => Some(volume),
+                    _ => return Err(format!("'{}' is not a volume between 0 and 1", text))
+                };
+            },
+            "--wav" => options.wav = Some(value(args, &arg, "a file")?),
+            "--gif" => options.gif = Some(value(args, &arg, "a file")?),
+            "--roms" => options.roms = Some(value(args, &arg, "a directory")?),
+            "--watch" => options.watch = Some(WatchMode::parse(&value(args, &arg, "a mode")?)?),
+            "--headless" => options.headless = true,
+            "--frames" => options.frames = Some(count(&value(args, &arg, "a number")?, "frames")?),
+            "--expect" => options.expect = Some(value(args, &arg, "a file")?),
+            // a lone - is standard input
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("Unknown option '{}'", arg)),
+            _ => {
+                if let Some(ref file) = options.file {
+                    return Err(format!("One file at a time, got '{}' and '{}'", file, arg));
+                }
+                options.file = Some(arg);
+            }
+        }
+    }
+
+    // only the window can do without a ROM, by showing the browser
+    if options.file.is_none() && !options.help && (options.command != Command::Run || options.headless) {
+        let what = if options.command == Command::Asm { "a source file" } else { "a ROM" };
+        let needer = if options.command == Command::Run { "--headless".to_string() } else { options.command.to_string() };
+        return Err(format!("{} needs {}", needer, what));
+    }
+    if options.watch.is_some() && options.file.as_deref().is_some_and(is_stdin) {
+        return Err("--watch needs a ROM file, not standard input".to_string());
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn parse_args(line: &str) -> Result<Options, String> {
+        parse(line.split_whitespace().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn run_is_the_default() {
+        assert_eq!(parse_args("").unwrap(), Options::default());
+        let options = parse_args("roms/c8games/PONG --speed 10 --palette green").unwrap();
+        assert_eq!(options.command, Command::Run);
+        assert_eq!(options.file, Some("roms/c8games/PONG".to_string()));
+        assert_eq!(options.instructions_per_frame, Some(10));
+        assert_eq!(options.theme, Some("green".to_string()));
+    }
+
+    #[test]
+    fn commands_and_options_parse() {
+        let options = parse_args("trace --quirks vip --seed 7 --start 0x2a0 --frames 2 PONG").unwrap();
+        assert_eq!(options.command, Command::Trace);
+        assert_eq!(options.quirks, Some(Quirks::preset("vip").unwrap()));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.start, Some(0x2a0));
+        assert_eq!(options.frames, Some(2));
+
+        let options = parse_args("--load 0x600 --font-at 0x50 --memory 2K --font vip ETI").unwrap();
+        assert_eq!(options.font, Some("vip".to_string()));
+        assert_eq!(options.load_address, Some(0x600));
+        assert_eq!(options.font_address, Some(0x50));
+        assert_eq!(options.memory_size, Some(2048));
+        assert!(parse_args("--vip-memory PONG").unwrap().vip_memory);
+        assert_eq!(parse_args("--stack unlimited PONG").unwrap().stack_depth, Some(StackDepth::Unlimited));
+
+        let options = parse_args("asm game.asm -o game.ch8").unwrap();
+        assert_eq!(options.command, Command::Asm);
+        assert_eq!(options.output, Some("game.ch8".to_string()));
+
+        assert!(parse_args("help").unwrap().help);
+        assert!(parse_args("--scale 4 --headless PONG").unwrap().headless);
+        assert_eq!(parse_args("info -").unwrap().file, Some("-".to_string()));
+    }
+
+    #[test]
+    fn mistakes_are_explained() {
+        assert_eq!(parse_args("--bogus").unwrap_err(), "Unknown option '--bogus'");
+        assert_eq!(parse_args("--speed").unwrap_err(), "--speed needs a number");
+        assert_eq!(parse_args("--speed 0").unwrap_err(), "'0' is not a number of instructions per frame");
+        assert_eq!(parse_args("disasm").unwrap_err(), "disasm needs a ROM");
+        assert_eq!(parse_args("asm").unwrap_err(), "asm needs a source file");
+        assert_eq!(parse_args("--watch reset -").unwrap_err(), "--watch needs a ROM file, not standard input");
+        assert_eq!(parse_args("--headless").unwrap_err(), "--headless needs a ROM");
+        assert!(parse_args("PONG MAZE").unwrap_err().contains("One file at a time"));
+        assert!(parse_args("--quirks chip-9").unwrap_err().contains("Unknown quirks preset"));
+        assert!(parse_args("--start 0x10000").is_err());
+        assert!(parse_args("--memory 3").unwrap_err().contains("not a memory size"));
+    }
+}
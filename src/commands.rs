@@ -0,0 +1,331 @@
+use std::fs;
+use std::path::Path;
+use asm::assemble;
+use cli::{Command, Options};
+use disasm::{disassemble, disassemble_word, is_instruction};
+use loader::{check_fits, read_program};
+use gif::GifRecorder;
+use memory::MemoryLayout;
+use opcodes::Chip8Opcode;
+use romdb::identify;
+use settings::Settings;
+use wav::AudioRecorder;
+use sha1::sha1_hex;
+use {font_for, layout_for, palettes_for, settings_for, ComputerState, GIF_SCALE};
+
+// How long each command runs for, in frames, unless --frames says otherwise
+const HEADLESS_FRAMES: u32 = 600;
+const TRACE_FRAMES: u32 = 1;
+const TEST_FRAMES: u32 = 300;
+
+// The commands that don't need a window, and headless runs. The saved
+// `settings` are for ROMs that don't have their own.
+pub fn run(options: &Options, settings: &Settings) -> Result<(), String> {
+    let file = match options.file {
+        Some(ref file) => file.as_str(),
+        None => return Err(format!("{} needs a file", options.command))
+    };
+    let layout = &layout_for(options)?;
+
+    match options.command {
+        Command::Asm => {
+            let source = fs::read_to_string(file).map_err(|e| format!("Could not read '{}': {}", file, e))?;
+            let program = assemble(&source, layout).map_err(|e| format!("{}: {}", file, e))?;
+            let output = match options.output {
+                Some(ref output) => output.clone(),
+                None => Path::new(file).with_extension("ch8").to_string_lossy().into_owned()
+            };
+            if output == file {
+                return Err(format!("Assembling '{}' would overwrite it, name the ROM with -o", file));
+            }
+            fs::write(&output, &program).map_err(|e| format!("Could not write '{}': {}", output, e))?;
+            println!("Assembled {} bytes into {}", program.len(), output);
+        },
+        Command::Disasm => {
+            let text = disassemble(&read_rom(file, layout)?, layout.load_address);
+            match options.output {
+                Some(ref output) => fs::write(output, text).map_err(|e| format!("Could not write '{}': {}", output, e))?,
+                None => print!("{}", text)
+            }
+        },
+        Command::Info => print!("{}", rom_info(file, &read_rom(file, layout)?, layout)),
+        Command::Trace => {
+            let program = read_rom(file, layout)?;
+            let settings = &settings_for(settings, options, Some(&sha1_hex(&program)));
+            let mut chip8 = machine(&program, layout, options, settings)?;
+            let frames = options.frames.unwrap_or(TRACE_FRAMES);
+            let ran = run_frames(&mut chip8, frames, settings.instructions_per_frame, |chip8| println!("{}", trace_line(chip8)), |_| {});
+            check_fault(&chip8, ran)?;
+            if ran < frames {
+                println!("Waiting for a key after {} frames", ran);
+            }
+        },
+        Command::Test => {
+            let program = read_rom(file, layout)?;
+            let settings = &settings_for(settings, options, Some(&sha1_hex(&program)));
+            let mut chip8 = machine(&program, layout, options, settings)?;
+            let frames = options.frames.unwrap_or(TEST_FRAMES);
+            let ran = run_frames(&mut chip8, frames, settings.instructions_per_frame, |_| {}, |_| {});
+            let screen = screen_text(&chip8.gfx);
+            print!("{}", screen);
+            check_fault(&chip8, ran)?;
+
+            if let Some(ref expected) = options.expect {
+                let expected_screen = fs::read_to_string(expected).map_err(|e| format!("Could not read '{}': {}", expected, e))?;
+                if expected_screen.trim() != screen.trim() {
+                    return Err(format!("The screen doesn't match '{}'", expected));
+                }
+                println!("The screen matches '{}'", expected);
+            }
+        },
+        Command::Run => headless(file, layout, options, settings)?
+    }
+    Ok(())
+}
+
+// Runs with nothing to see or hear, other than what's recorded
+fn headless(file: &str, layout: &MemoryLayout, options: &Options, settings: &Settings) -> Result<(), String> {
+    let program = read_rom(file, layout)?;
+    let settings = &settings_for(settings, options, Some(&sha1_hex(&program)));
+    let mut chip8 = machine(&program, layout, options, settings)?;
+    let (palettes, palette_index) = palettes_for(options, settings)?;
+    let palette = &palettes[palette_index];
+
+    let mut gif = match options.gif {
+        Some(ref path) => Some(GifRecorder::create(path, palette, GIF_SCALE).map_err(|e| format!("Could not record to '{}': {}", path, e))?),
+        None => None
+    };
+    let mut wav = match options.wav {
+        Some(ref path) => Some(AudioRecorder::create(path, 44_100).map_err(|e| format!("Could not create '{}': {}", path, e))?),
+        None => None
+    };
+
+    let tone = settings.tone();
+    let mut failure = None;
+    let frames = options.frames.unwrap_or(HEADLESS_FRAMES);
+    let ran = run_frames(&mut chip8, frames, settings.instructions_per_frame, |_| {}, |chip8| {
+        if let Some(ref mut gif) = gif {
+            if let Err(e) = gif.frame(&chip8.gfx, palette) {
+                failure.get_or_insert_with(|| format!("Could not record: {}", e));
+            }
+        }
+        if let Some(ref mut wav) = wav {
+            if let Err(e) = wav.frame(&tone, chip8.take_sound_timer_write()) {
+                failure.get_or_insert_with(|| format!("Could not record sound: {}", e));
+            }
+        }
+    });
+
+    if let Some(gif) = gif {
+        gif.finish().map_err(|e| format!("Could not finish the recording: {}", e))?;
+    }
+    if let Some(wav) = wav {
+        wav.finish().map_err(|e| format!("Could not finish the sound recording: {}", e))?;
+    }
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    check_fault(&chip8, ran)?;
+    if ran < frames {
+        println!("Stopped after {} frames, waiting for a key", ran);
+    }
+    Ok(())
+}
+
+// A ROM from a file, or standard input for "-", that fits the machine
+fn read_rom(path: &str, layout: &MemoryLayout) -> Result<Vec<u8>, String> {
+    let program = read_program(path).and_then(|program| check_fits(&program, layout.program_room()).map(|_| program));
+    program.map_err(|e| format!("Could not load '{}': {}", path, e))
+}
+
+// Set up as the options say
+fn machine(program: &[u8], layout: &MemoryLayout, options: &Options, settings: &Settings) -> Result<ComputerState, String> {
+    let mut chip8 = ComputerState::with_layout(layout.clone());
+    chip8.load_font(&font_for(settings, layout)?);
+    if let Some(seed) = options.seed {
+        chip8.seed(seed);
+    }
+    chip8.load_bytes(program).map_err(|e| e.to_string())?;
+    chip8.quirks = settings.quirks.clone();
+    if let Some(start) = options.start {
+        chip8.program_counter = start;
+    }
+    Ok(chip8)
+}
+
+// Frames like the window runs them, as fast as possible. `before_step`
+// sees the machine before every instruction and `after_frame` after
+// every frame. Stops early if the program waits for a key, as there's
+// nobody to press one, or crashes the machine, and says how many
+// frames ran.
+pub fn run_frames<S, F>(chip8: &mut ComputerState, frames: u32, instructions_per_frame: u32,
+                        mut before_step: S, mut after_frame: F) -> u32
+    where S: FnMut(&ComputerState), F: FnMut(&mut ComputerState) {
+    for frame in 0..frames {
+        if chip8.waiting_for_keypress || chip8.fault.is_some() {
+            return frame;
+        }
+        for _ in 0..instructions_per_frame {
+            if chip8.is_blocked() {
+                break;
+            }
+            before_step(chip8);
+            chip8.step();
+        }
+        after_frame(chip8);
+        chip8.vblank();
+    }
+    frames
+}
+
+// A program that crashed the machine fails the command, with the stack
+// to show how it got there
+fn check_fault(chip8: &ComputerState, frames: u32) -> Result<(), String> {
+    match chip8.fault {
+        Some(ref fault) if chip8.stack.is_empty() => Err(format!("Stopped after {} frames: {}", frames, fault)),
+        Some(ref fault) => {
+            let stack: Vec<String> = chip8.stack.iter().map(|address| format!("{:03X}", address)).collect();
+            Err(format!("Stopped after {} frames: {}\nStack: {}", frames, fault, stack.join(" ")))
+        },
+        None => Ok(())
+    }
+}
+
+// Where the machine is, what it's about to do and its registers
+pub fn trace_line(chip8: &ComputerState) -> String {
+    let pc = chip8.program_counter;
+    let word = (chip8.peek(pc) as u16) << 8 | chip8.peek(pc.wrapping_add(1)) as u16;
+    let registers: Vec<String> = chip8.registers.iter().map(|value| format!("{:02X}", value)).collect();
+    format!("{:03X}  {:04X}  {:<20}I {:03X}  V {}", pc, word, disassemble_word(chip8, word), chip8.index, registers.join(" "))
+}
+
+// The display as text, # for lit pixels and . for the rest
+pub fn screen_text(gfx: &[u8]) -> String {
+    let mut text = String::new();
+    for row in gfx.chunks(64) {
+        text.extend(row.iter().map(|pixel| if *pixel != 0 { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+// What can be told about a ROM without running it. As code and data
+// are mixed together, this goes by every word that could be an instruction.
+pub fn rom_info(path: &str, program: &[u8], layout: &MemoryLayout) -> String {
+    let decoder = ComputerState::new();
+    let words: Vec<u16> = program.chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+        .collect();
+    let instructions: Vec<Chip8Opcode> = words.iter().cloned()
+        .filter(|word| is_instruction(*word))
+        .map(|word| decoder.decode(word))
+        .collect();
+    let uses = |found: fn(&Chip8Opcode) -> bool| instructions.iter().any(found);
+
+    let mut features = Vec::new();
+    if uses(|op| matches!(*op, Chip8Opcode::SkipNextIfKeyDown(_) | Chip8Opcode::SkipNextIfKeyUp(_) | Chip8Opcode::BlockOnKeyPress(_))) {
+        features.push("keys");
+    }
+    if uses(|op| matches!(*op, Chip8Opcode::SetSoundTimer(_))) {
+        features.push("sound");
+    }
+    if uses(|op| matches!(*op, Chip8Opcode::Random(_, _))) {
+        features.push("random numbers");
+    }
+    if uses(|op| matches!(*op, Chip8Opcode::CallSub(_))) {
+        features.push("subroutines");
+    }
+    if uses(|op| matches!(*op, Chip8Opcode::UseSprite(_) | Chip8Opcode::UseBigSprite(_) | Chip8Opcode::ReadRegisterAsBCD(_))) {
+        features.push("the font");
+    }
+
+    let end = layout.load_address as usize + program.len().max(1) - 1;
+    let mut text = String::new();
+    text += &format!("File          {}\n", path);
+    if let Some(rom) = identify(program) {
+        text += &format!("Title         {} by {}\n", rom.title, rom.author);
+        text += &format!("Platform      {}, best at {} instructions per frame\n", rom.platform, rom.instructions_per_frame);
+    }
+    text += &format!("Size          {} bytes, 0x{:03X} to 0x{:03X}\n", program.len(), layout.load_address, end);
+    text += &format!("SHA-1         {}\n", sha1_hex(program));
+    text += &format!("Instructions  {} of {} words decode\n", instructions.len(), words.len());
+    text += &format!("Uses          {}\n", if features.is_empty() { "nothing special".to_string() } else { features.join(", ") });
+    text
+}
+
+#[cfg(test)]
+mod commands_tests {
+    use super::*;
+
+    fn test_machine(program: &[u8]) -> ComputerState {
+        machine(program, &MemoryLayout::default(), &Options { seed: Some(1), ..Options::default() }, &Settings::default()).unwrap()
+    }
+
+    #[test]
+    fn screens_are_drawn_in_text() {
+        let mut gfx = [0u8; 64 * 32];
+        gfx[0] = 1;
+        gfx[65] = 1;
+        let text = screen_text(&gfx);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines[0].starts_with("#.."));
+        assert!(lines[1].starts_with(".#."));
+    }
+
+    #[test]
+    fn frames_run_until_a_key_is_needed() {
+        // LD V0, 1 / ADD V0, 1 / LD V1, K
+        let mut chip8 = test_machine(&[0x60, 0x01, 0x70, 0x01, 0xf1, 0x0a]);
+        let mut traced = Vec::new();
+        let ran = run_frames(&mut chip8, 10, 10, |chip8| traced.push(trace_line(chip8)), |_| {});
+        assert_eq!(ran, 1);
+        assert_eq!(traced.len(), 3);
+        assert!(traced[1].starts_with("202  7001  ADD V0, 0x01        I 000  V 01 00"));
+    }
+
+    #[test]
+    fn maze_draws_something() {
+        let program = read_rom("roms/c8games/MAZE", &MemoryLayout::default()).unwrap();
+        let mut chip8 = test_machine(&program);
+        assert_eq!(run_frames(&mut chip8, 60, 10, |_| {}, |_| {}), 60);
+        assert!(screen_text(&chip8.gfx).contains('#'));
+    }
+
+    #[test]
+    fn info_describes_roms() {
+        let program = read_rom("roms/c8games/PONG", &MemoryLayout::default()).unwrap();
+        let info = rom_info("PONG", &program, &MemoryLayout::default());
+        assert!(info.contains("Size          246 bytes, 0x200 to 0x2F5"));
+        let eti = MemoryLayout::new(0x600, 0x000, 4096).unwrap();
+        assert!(rom_info("PONG", &program, &eti).contains("Size          246 bytes, 0x600 to 0x6F5"));
+        assert!(info.contains("keys, sound, random numbers, subroutines, the font"));
+        assert!(info.contains("SHA-1         b232ef880bd6060fb45fa6effed7edf0ae95670e"));
+        assert!(info.contains("Title         Pong by Paul Vervalin"));
+    }
+
+    #[test]
+    fn runaway_recursion_stops_the_machine() {
+        // 200: CALL 0x200
+        let mut chip8 = test_machine(&[0x22, 0x00]);
+        let ran = run_frames(&mut chip8, 10, 10, |_| {}, |_| {});
+        assert_eq!(ran, 2);
+        let error = check_fault(&chip8, ran).unwrap_err();
+        assert!(error.starts_with("Stopped after 2 frames: stack overflow at 0x200, a call with 16 already"), "{}", error);
+        assert!(error.ends_with("Stack: 202 202 202 202 202 202 202 202 202 202 202 202 202 202 202 202"), "{}", error);
+
+        // RET
+        let mut chip8 = test_machine(&[0x00, 0xee]);
+        let ran = run_frames(&mut chip8, 10, 10, |_| {}, |_| {});
+        assert_eq!(check_fault(&chip8, ran).unwrap_err(), "Stopped after 1 frames: stack underflow at 0x200, a return with nothing to return to");
+    }
+
+    #[test]
+    fn bad_roms_are_explained() {
+        assert_eq!(read_rom("no/such/ROM", &MemoryLayout::default()).unwrap_err(), "Could not load 'no/such/ROM': there's no file called 'no/such/ROM'");
+        assert_eq!(machine(&[], &MemoryLayout::default(), &Options::default(), &Settings::default()).err(), Some("the ROM is empty".to_string()));
+        let tiny = MemoryLayout::new(0x380, 0x000, 1024).unwrap();
+        assert_eq!(read_rom("roms/c8games/PONG", &tiny).unwrap_err(), "Could not load 'roms/c8games/PONG': the ROM is 246 bytes, but there's only room for 128");
+    }
+}
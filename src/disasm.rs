@@ -0,0 +1,119 @@
+use opcodes::Chip8Opcode;
+use ComputerState;
+
+// Whether a word is an instruction we know, as decode panics on the rest
+pub fn is_instruction(word: u16) -> bool {
+    let low_nibble = word & 0x000f;
+    let low_byte = word & 0x00ff;
+    match word >> 12 {
+        0x5 | 0x9 => low_nibble == 0,
+        0x8 => low_nibble <= 7 || low_nibble == 0xe,
+        0xe => low_byte == 0x9e || low_byte == 0xa1,
+        0xf => [0x07, 0x0a, 0x15, 0x18, 0x1e, 0x29, 0x30, 0x33, 0x55, 0x65].contains(&low_byte),
+        _ => true
+    }
+}
+
+// In the usual assembler syntax, which `asm` reads back
+pub fn mnemonic(op: &Chip8Opcode) -> String {
+    match *op {
+        Chip8Opcode::Call(address) => format!("SYS 0x{:03X}", address),
+        Chip8Opcode::DisplayClear => "CLS".to_string(),
+        Chip8Opcode::ReturnFromSubroutine => "RET".to_string(),
+        Chip8Opcode::Goto(address) => format!("JP 0x{:03X}", address),
+        Chip8Opcode::CallSub(address) => format!("CALL 0x{:03X}", address),
+        Chip8Opcode::SkipNextIfEqual(x, value) => format!("SE V{:X}, 0x{:02X}", x, value),
+        Chip8Opcode::SkipNextIfNotEqual(x, value) => format!("SNE V{:X}, 0x{:02X}", x, value),
+        Chip8Opcode::SkipNextIfRegistersEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Chip8Opcode::SetRegister(x, value) => format!("LD V{:X}, 0x{:02X}", x, value),
+        Chip8Opcode::IncrementRegister(x, value) => format!("ADD V{:X}, 0x{:02X}", x, value),
+        Chip8Opcode::SetRegisterToRegister(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Chip8Opcode::RegisterRegisterOr(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Chip8Opcode::RegisterRegisterAnd(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Chip8Opcode::RegisterRegisterXor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Chip8Opcode::IncrementRegisterWithRegister(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Chip8Opcode::DecrementRegisterWithRegister(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Chip8Opcode::ShiftRegisterByRegister(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Chip8Opcode::YRegisterMinusXRegister(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Chip8Opcode::LeftShiftRegisterByRegister(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        Chip8Opcode::SkipNextIfRegistersNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Chip8Opcode::SetIndexRegister(address) => format!("LD I, 0x{:03X}", address),
+        Chip8Opcode::JumpFromV0(address) => format!("JP V0, 0x{:03X}", address),
+        Chip8Opcode::Random(x, mask) => format!("RND V{:X}, 0x{:02X}", x, mask),
+        Chip8Opcode::Draw(x, y, height) => format!("DRW V{:X}, V{:X}, {}", x, y, height),
+        Chip8Opcode::SkipNextIfKeyDown(x) => format!("SKP V{:X}", x),
+        Chip8Opcode::SkipNextIfKeyUp(x) => format!("SKNP V{:X}", x),
+        Chip8Opcode::ReadDelayTimer(x) => format!("LD V{:X}, DT", x),
+        Chip8Opcode::BlockOnKeyPress(x) => format!("LD V{:X}, K", x),
+        Chip8Opcode::SetDelayTimer(x) => format!("LD DT, V{:X}", x),
+        Chip8Opcode::SetSoundTimer(x) => format!("LD ST, V{:X}", x),
+        Chip8Opcode::AddToIndexRegister(x) => format!("ADD I, V{:X}", x),
+        Chip8Opcode::UseSprite(x) => format!("LD F, V{:X}", x),
+        Chip8Opcode::UseBigSprite(x) => format!("LD HF, V{:X}", x),
+        Chip8Opcode::ReadRegisterAsBCD(x) => format!("LD B, V{:X}", x),
+        Chip8Opcode::DumpRegisters(x) => format!("LD [I], V{:X}", x),
+        Chip8Opcode::FillRegisters(x) => format!("LD V{:X}, [I]", x)
+    }
+}
+
+// A word as an instruction, or as data if it isn't one. Any machine
+// will do to decode it.
+pub fn disassemble_word(decoder: &ComputerState, word: u16) -> String {
+    if is_instruction(word) {
+        mnemonic(&decoder.decode(word))
+    } else {
+        format!("DW 0x{:04X}", word)
+    }
+}
+
+// Every word of a program, in order, with its address and encoding in a
+// comment. There's no telling code from data, so sprites come out as
+// instructions too, but it all assembles back to the same bytes.
+pub fn disassemble(program: &[u8], address: u16) -> String {
+    let decoder = ComputerState::new();
+    let mut text = String::new();
+    for (index, pair) in program.chunks(2).enumerate() {
+        let line_address = address as usize + index * 2;
+        let line = if pair.len() == 2 {
+            let word = (pair[0] as u16) << 8 | pair[1] as u16;
+            format!("{:<20}; {:03X}  {:04X}", disassemble_word(&decoder, word), line_address, word)
+        } else {
+            // an odd byte left at the end
+            format!("{:<20}; {:03X}  {:02X}", format!("DB 0x{:02X}", pair[0]), line_address, pair[0])
+        };
+        text += &line;
+        text += "\n";
+    }
+    text
+}
+
+#[cfg(test)]
+mod disasm_tests {
+    use super::*;
+
+    #[test]
+    fn words_disassemble() {
+        let decoder = ComputerState::new();
+        let disassemble_word = |word| disassemble_word(&decoder, word);
+        assert_eq!(disassemble_word(0x00e0), "CLS");
+        assert_eq!(disassemble_word(0x6a02), "LD VA, 0x02");
+        assert_eq!(disassemble_word(0xd016), "DRW V0, V1, 6");
+        assert_eq!(disassemble_word(0xf265), "LD V2, [I]");
+        assert_eq!(disassemble_word(0x22d4), "CALL 0x2D4");
+        assert_eq!(disassemble_word(0xf530), "LD HF, V5");
+        // not instructions
+        assert_eq!(disassemble_word(0x5121), "DW 0x5121");
+        assert_eq!(disassemble_word(0xf0ff), "DW 0xF0FF");
+    }
+
+    #[test]
+    fn programs_disassemble_line_by_line() {
+        let text = disassemble(&[0x00, 0xe0, 0x12, 0x00, 0xff], 0x200);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, vec![
+            "CLS                 ; 200  00E0",
+            "JP 0x200            ; 202  1200",
+            "DB 0xFF             ; 204  FF"
+        ]);
+    }
+}
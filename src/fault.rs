@@ -0,0 +1,36 @@
+use std::fmt;
+
+// Something a program did that would have crashed the real machine.
+// The machine stops where it is, rather than carrying on in a way no
+// hardware would.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    // A call with the stack already `depth` deep
+    StackOverflow { address: u16, depth: usize },
+    // A return with nothing to return to
+    StackUnderflow { address: u16 }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::StackOverflow { address, depth } =>
+                write!(f, "stack overflow at 0x{:03X}, a call with {} already on the stack", address, depth),
+            Fault::StackUnderflow { address } =>
+                write!(f, "stack underflow at 0x{:03X}, a return with nothing to return to", address)
+        }
+    }
+}
+
+#[cfg(test)]
+mod fault_tests {
+    use super::*;
+
+    #[test]
+    fn faults_say_where_they_happened() {
+        assert_eq!(Fault::StackOverflow { address: 0x2a4, depth: 12 }.to_string(),
+                   "stack overflow at 0x2A4, a call with 12 already on the stack");
+        assert_eq!(Fault::StackUnderflow { address: 0x200 }.to_string(),
+                   "stack underflow at 0x200, a return with nothing to return to");
+    }
+}
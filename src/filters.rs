@@ -0,0 +1,287 @@
+use sdl2::pixels::Color;
+use std::fmt;
+use image::Image;
+
+// Software post-processing applied while scaling the display up to the
+// window. Everything runs on the CPU, so it works without a GPU and the
+// same output can be saved as a screenshot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // Plain square pixels
+    None,
+    // Every other line is darkened, like a TV
+    Scanlines,
+    // A dark gap between every pixel, like a handheld LCD
+    PixelGrid,
+    // Scale2x/EPX edge smoothing
+    Scale2x,
+    // Scanlines plus a soft bloom around lit pixels
+    Crt
+}
+
+const ALL_FILTERS: [Filter; 5] = [Filter::None, Filter::Scanlines, Filter::PixelGrid, Filter::Scale2x, Filter::Crt];
+
+impl Filter {
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        ALL_FILTERS.iter()
+            .find(|filter| filter.to_string() == text.to_lowercase())
+            .cloned()
+            .ok_or_else(|| format!("Unknown filter '{}', try none, scanlines, grid, scale2x or crt", text))
+    }
+
+    // The filter after this one when cycling with the hotkey
+    pub fn next(&self) -> Filter {
+        let index = ALL_FILTERS.iter().position(|filter| filter == self).unwrap();
+        ALL_FILTERS[(index + 1) % ALL_FILTERS.len()]
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Filter::None => "none",
+            Filter::Scanlines => "scanlines",
+            Filter::PixelGrid => "grid",
+            Filter::Scale2x => "scale2x",
+            Filter::Crt => "crt"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Scale the source up by a whole number factor, filtering on the way
+pub fn apply(filter: Filter, source: &Image, scale: u32) -> Image {
+    match filter {
+        Filter::None => scale_nearest(source, scale),
+        Filter::Scanlines => scanlines(&scale_nearest(source, scale), scale, 0.5),
+        Filter::PixelGrid => pixel_grid(&scale_nearest(source, scale), scale),
+        Filter::Scale2x => {
+            // smooth as many times as divides evenly into the scale,
+            // then make up the difference with square pixels
+            let mut result = source.clone();
+            let mut factor = 1;
+            while scale.is_multiple_of(factor * 2) {
+                result = scale2x(&result);
+                factor *= 2;
+            }
+            scale_nearest(&result, scale / factor)
+        },
+        Filter::Crt => {
+            let lines = scanlines(&scale_nearest(source, scale), scale, 0.35);
+            glow(&lines, scale / 2)
+        }
+    }
+}
+
+fn scale_nearest(source: &Image, scale: u32) -> Image {
+    let mut result = Image::new(source.width * scale, source.height * scale, Color::RGB(0, 0, 0));
+    for y in 0..result.height {
+        for x in 0..result.width {
+            result.set(x, y, source.get(x / scale, y / scale));
+        }
+    }
+    result
+}
+
+fn darken(color: Color, amount: f32) -> Color {
+    let channel = |c: u8| (c as f32 * (1.0 - amount)) as u8;
+    Color::RGB(channel(color.r), channel(color.g), channel(color.b))
+}
+
+// Darken the bottom half of every scaled-up pixel row
+fn scanlines(scaled: &Image, scale: u32, amount: f32) -> Image {
+    let mut result = scaled.clone();
+    for y in 0..result.height {
+        if scale > 1 && y % scale >= scale / 2 {
+            for x in 0..result.width {
+                let pixel = result.get(x, y);
+                result.set(x, y, darken(pixel, amount));
+            }
+        }
+    }
+    result
+}
+
+// Darken the right and bottom edge of every scaled-up pixel
+fn pixel_grid(scaled: &Image, scale: u32) -> Image {
+    let mut result = scaled.clone();
+    if scale < 3 {
+        // no room for a gap
+        return result;
+    }
+
+    for y in 0..result.height {
+        for x in 0..result.width {
+            if x % scale == scale - 1 || y % scale == scale - 1 {
+                let pixel = result.get(x, y);
+                result.set(x, y, darken(pixel, 0.4));
+            }
+        }
+    }
+    result
+}
+
+// EPX, from Andrea Mazzoleni's Scale2x: each pixel becomes 2x2, and corners
+// take the colour of their neighbours when those form a diagonal edge
+fn scale2x(source: &Image) -> Image {
+    let mut result = Image::new(source.width * 2, source.height * 2, Color::RGB(0, 0, 0));
+    let (w, h) = (source.width as i32, source.height as i32);
+
+    // edges repeat the nearest pixel
+    let at = |x: i32, y: i32| source.get(x.max(0).min(w - 1) as u32, y.max(0).min(h - 1) as u32);
+
+    for y in 0..h {
+        for x in 0..w {
+            let p = at(x, y);
+            let a = at(x, y - 1);
+            let b = at(x + 1, y);
+            let c = at(x - 1, y);
+            let d = at(x, y + 1);
+
+            let top_left = if c == a && c != d && a != b { a } else { p };
+            let top_right = if a == b && a != c && b != d { b } else { p };
+            let bottom_left = if d == c && d != b && c != a { c } else { p };
+            let bottom_right = if b == d && b != a && d != c { d } else { p };
+
+            let (ox, oy) = (x as u32 * 2, y as u32 * 2);
+            result.set(ox, oy, top_left);
+            result.set(ox + 1, oy, top_right);
+            result.set(ox, oy + 1, bottom_left);
+            result.set(ox + 1, oy + 1, bottom_right);
+        }
+    }
+    result
+}
+
+// Add a blurred copy of the picture on top of itself
+fn glow(source: &Image, radius: u32) -> Image {
+    if radius == 0 {
+        return source.clone();
+    }
+
+    let blurred = box_blur(&box_blur(source, radius, true), radius, false);
+    let mut result = source.clone();
+    for (out, halo) in result.pixels.iter_mut().zip(blurred.pixels.iter()) {
+        let add = |a: u8, b: u8| (a as u32 + b as u32 / 2).min(255) as u8;
+        *out = Color::RGB(add(out.r, halo.r), add(out.g, halo.g), add(out.b, halo.b));
+    }
+    result
+}
+
+// One direction of a separable box blur, using a running sum
+fn box_blur(source: &Image, radius: u32, horizontal: bool) -> Image {
+    let mut result = source.clone();
+    let (length, lines) = if horizontal { (source.width, source.height) } else { (source.height, source.width) };
+    let radius = radius as i32;
+    let window = (radius * 2 + 1) as u32;
+
+    for line in 0..lines {
+        let at = |i: i32| {
+            let i = i.max(0).min(length as i32 - 1) as u32;
+            if horizontal { source.get(i, line) } else { source.get(line, i) }
+        };
+
+        let mut sum = [0u32; 3];
+        for i in -radius..=radius {
+            let pixel = at(i);
+            sum[0] += pixel.r as u32;
+            sum[1] += pixel.g as u32;
+            sum[2] += pixel.b as u32;
+        }
+
+        for i in 0..length as i32 {
+            let color = Color::RGB((sum[0] / window) as u8, (sum[1] / window) as u8, (sum[2] / window) as u8);
+            if horizontal { result.set(i as u32, line, color) } else { result.set(line, i as u32, color) }
+
+            let leaving = at(i - radius);
+            let entering = at(i + radius + 1);
+            sum[0] = sum[0] + entering.r as u32 - leaving.r as u32;
+            sum[1] = sum[1] + entering.g as u32 - leaving.g as u32;
+            sum[2] = sum[2] + entering.b as u32 - leaving.b as u32;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+
+    fn checkerboard() -> Image {
+        let mut image = Image::new(2, 2, Color::RGB(0, 0, 0));
+        image.set(0, 0, Color::RGB(255, 255, 255));
+        image.set(1, 1, Color::RGB(255, 255, 255));
+        image
+    }
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(Filter::parse("crt"), Ok(Filter::Crt));
+        assert_eq!(Filter::parse("Scanlines"), Ok(Filter::Scanlines));
+        assert_eq!(Filter::parse("grid"), Ok(Filter::PixelGrid));
+        assert!(Filter::parse("hq4x").is_err());
+    }
+
+    #[test]
+    fn next_cycles_back_around() {
+        let mut filter = Filter::None;
+        for _ in 0..ALL_FILTERS.len() {
+            filter = filter.next();
+        }
+        assert_eq!(filter, Filter::None);
+    }
+
+    #[test]
+    fn every_filter_scales_to_the_same_size() {
+        let source = checkerboard();
+        for filter in ALL_FILTERS.iter() {
+            let result = apply(*filter, &source, 6);
+            assert_eq!(result.width, 12);
+            assert_eq!(result.height, 12);
+        }
+    }
+
+    #[test]
+    fn nearest_makes_square_pixels() {
+        let result = apply(Filter::None, &checkerboard(), 2);
+        assert_eq!(result.get(0, 0), Color::RGB(255, 255, 255));
+        assert_eq!(result.get(1, 1), Color::RGB(255, 255, 255));
+        assert_eq!(result.get(2, 0), Color::RGB(0, 0, 0));
+        assert_eq!(result.get(3, 3), Color::RGB(255, 255, 255));
+    }
+
+    #[test]
+    fn scanlines_darken_the_bottom_half() {
+        let result = apply(Filter::Scanlines, &checkerboard(), 2);
+        assert_eq!(result.get(0, 0), Color::RGB(255, 255, 255));
+        assert_eq!(result.get(0, 1), Color::RGB(127, 127, 127));
+    }
+
+    #[test]
+    fn scale2x_rounds_off_diagonals() {
+        // a diagonal line of white on black
+        let mut source = Image::new(3, 3, Color::RGB(0, 0, 0));
+        for i in 0..3 {
+            source.set(i, i, Color::RGB(255, 255, 255));
+        }
+
+        let result = scale2x(&source);
+        // the black pixel above the middle of the line gets its
+        // bottom-left corner filled in, joining up the staircase
+        assert_eq!(result.get(2, 1), Color::RGB(255, 255, 255));
+        assert_eq!(result.get(3, 1), Color::RGB(0, 0, 0));
+        // solid areas are left alone
+        assert_eq!(result.get(5, 0), Color::RGB(0, 0, 0));
+    }
+
+    #[test]
+    fn glow_brightens_around_lit_pixels() {
+        let mut source = Image::new(5, 1, Color::RGB(0, 0, 0));
+        source.set(2, 0, Color::RGB(255, 255, 255));
+
+        let result = glow(&source, 1);
+        assert!(result.get(1, 0).r > 0);
+        assert!(result.get(3, 0).r > 0);
+        assert_eq!(result.get(0, 0), Color::RGB(0, 0, 0));
+    }
+}
@@ -0,0 +1,168 @@
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use keymap::{for_each_binding, parse_chip8_key};
+
+// Which controller buttons press which of the 16 CHIP-8 keys
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadMap {
+    bindings: HashMap<Button, u8>
+}
+
+// SDL's own names for the buttons, as used in controller mapping strings
+const BUTTON_NAMES: [(&str, Button); 15] = [
+    ("a", Button::A), ("b", Button::B), ("x", Button::X), ("y", Button::Y),
+    ("back", Button::Back), ("guide", Button::Guide), ("start", Button::Start),
+    ("leftstick", Button::LeftStick), ("rightstick", Button::RightStick),
+    ("leftshoulder", Button::LeftShoulder), ("rightshoulder", Button::RightShoulder),
+    ("dpup", Button::DPadUp), ("dpdown", Button::DPadDown),
+    ("dpleft", Button::DPadLeft), ("dpright", Button::DPadRight)
+];
+
+impl Default for PadMap {
+    // Most games steer with 2/4/6/8 and fire with 5, as those
+    // sit in a cross on the COSMAC VIP keypad
+    fn default() -> PadMap {
+        let layout = [
+            (Button::DPadUp, 0x2), (Button::DPadDown, 0x8), (Button::DPadLeft, 0x4), (Button::DPadRight, 0x6),
+            (Button::A, 0x5), (Button::B, 0x0), (Button::X, 0x1), (Button::Y, 0x3),
+            (Button::LeftShoulder, 0x7), (Button::RightShoulder, 0x9),
+            (Button::Back, 0xe), (Button::Start, 0xf)
+        ];
+        PadMap { bindings: layout.iter().cloned().collect() }
+    }
+}
+
+impl PadMap {
+    pub fn lookup(&self, button: Button) -> Option<u8> {
+        self.bindings.get(&button).cloned()
+    }
+
+    // Changes to the default mapping, one CHIP-8 key per line:
+    //
+    //   # BLINKY steers with 3/6/7/8
+    //   3 = dpup
+    //   6 = dpdown
+    //   7 = dpleft
+    //   8 = dpright
+    //
+    // Buttons use SDL's names: a, b, x, y, back, start, leftshoulder, dpup...
+    pub fn parse(text: &str) -> Result<PadMap, String> {
+        let mut map = PadMap::default();
+
+        for_each_binding(text, |name, value| {
+            let key = parse_chip8_key(name)?;
+            for button_name in value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                let button = button_from_name(button_name)
+                    .ok_or_else(|| format!("unknown controller button '{}'", button_name))?;
+                map.bindings.insert(button, key);
+            }
+            Ok(())
+        })?;
+
+        Ok(map)
+    }
+
+    pub fn load(path: &str) -> Result<PadMap, String> {
+        let mut file = File::open(path).map_err(|e| format!("Could not open '{}': {}", path, e))?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+        PadMap::parse(&text).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // A ROM can carry its own mapping in a file next to it, e.g. BLINKY.pad
+    pub fn for_program(program_path: &str) -> Result<PadMap, String> {
+        let override_path = format!("{}.pad", program_path);
+        if Path::new(&override_path).exists() {
+            println!("Using controller mapping '{}'", override_path);
+            PadMap::load(&override_path)
+        } else {
+            Ok(PadMap::default())
+        }
+    }
+}
+
+pub fn button_from_name(name: &str) -> Option<Button> {
+    let name = name.to_lowercase();
+    BUTTON_NAMES.iter().find(|&&(n, _)| n == name).map(|&(_, button)| button)
+}
+
+// The controllers that are plugged in right now. SDL reports every
+// controller as added when it starts up, so this only needs to follow events.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // keyed by joystick instance id, which is what button events carry
+    open: HashMap<i32, GameController>
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem) -> Gamepads {
+        Gamepads {
+            subsystem,
+            open: HashMap::new()
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        println!("Controller connected: {}", controller.name());
+                        self.open.insert(controller.instance_id(), controller);
+                    },
+                    Err(e) => println!("Could not open controller {}: {}", which, e)
+                }
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.open.remove(&which) {
+                    println!("Controller disconnected: {}", controller.name());
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod gamepad_tests {
+    use super::*;
+
+    #[test]
+    fn default_mapping_steers_with_the_dpad() {
+        let map = PadMap::default();
+        assert_eq!(map.lookup(Button::DPadUp), Some(2));
+        assert_eq!(map.lookup(Button::DPadLeft), Some(4));
+        assert_eq!(map.lookup(Button::DPadRight), Some(6));
+        assert_eq!(map.lookup(Button::DPadDown), Some(8));
+        assert_eq!(map.lookup(Button::A), Some(5));
+        assert_eq!(map.lookup(Button::Guide), None);
+    }
+
+    #[test]
+    fn button_names_work() {
+        assert_eq!(button_from_name("dpup"), Some(Button::DPadUp));
+        assert_eq!(button_from_name("LeftShoulder"), Some(Button::LeftShoulder));
+        assert_eq!(button_from_name("trigger"), None);
+    }
+
+    #[test]
+    fn parse_overrides_the_defaults() {
+        let map = PadMap::parse("# BLINKY\n3 = dpup\n7 = dpleft, x\n").unwrap();
+        assert_eq!(map.lookup(Button::DPadUp), Some(3));
+        assert_eq!(map.lookup(Button::DPadLeft), Some(7));
+        assert_eq!(map.lookup(Button::X), Some(7));
+        // untouched buttons keep their default
+        assert_eq!(map.lookup(Button::A), Some(5));
+    }
+
+    #[test]
+    fn parse_reports_bad_lines() {
+        assert!(PadMap::parse("3 = trigger").unwrap_err().contains("line 1"));
+        assert!(PadMap::parse("\nZ = a").unwrap_err().contains("line 2"));
+    }
+}
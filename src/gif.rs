@@ -0,0 +1,371 @@
+use sdl2::pixels::Color;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use palette::Palette;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+// Browsers show anything shorter than 2 centiseconds as 10, so
+// frames are never written with a shorter delay than this
+const MIN_DELAY: u64 = 2;
+
+// One screen of the display, and the colours it was shown in
+#[derive(Clone, PartialEq)]
+struct Frame {
+    gfx: Vec<u8>,
+    // background, foreground
+    colors: [Color; 2]
+}
+
+// Records the CHIP-8 display to an animated GIF, one call per emulated
+// frame. Each GIF frame is only written once the next different one turns
+// up, as that's when we know how long it was on screen for.
+pub struct GifRecorder<W: Write> {
+    out: W,
+    scale: usize,
+    // The colour table in the header, used until the palette changes
+    colors: [Color; 2],
+    pending: Option<Frame>,
+    // Both counted in 60hz frames from the start of the recording
+    pending_start: u64,
+    now: u64
+}
+
+impl GifRecorder<BufWriter<File>> {
+    pub fn create(path: &str, palette: &Palette, scale: usize) -> io::Result<GifRecorder<BufWriter<File>>> {
+        GifRecorder::new(BufWriter::new(File::create(path)?), palette, scale)
+    }
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(mut out: W, palette: &Palette, scale: usize) -> io::Result<GifRecorder<W>> {
+        let colors = [palette.background, palette.foreground];
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&((WIDTH * scale) as u16).to_le_bytes())?;
+        out.write_all(&((HEIGHT * scale) as u16).to_le_bytes())?;
+        out.write_all(&[0x80, 0, 0])?; // a global colour table of 2 entries
+        write_color_table(&mut out, &colors)?;
+
+        // Netscape extension, so the animation loops forever
+        out.write_all(&[0x21, 0xff, 11])?;
+        out.write_all(b"NETSCAPE2.0")?;
+        out.write_all(&[3, 1, 0, 0, 0])?;
+
+        Ok(GifRecorder {
+            out,
+            scale,
+            colors,
+            pending: None,
+            pending_start: 0,
+            now: 0
+        })
+    }
+
+    pub fn frame(&mut self, gfx: &[u8], palette: &Palette) -> io::Result<()> {
+        let frame = Frame {
+            gfx: gfx.to_vec(),
+            colors: [palette.background, palette.foreground]
+        };
+
+        let changed = match self.pending {
+            Some(ref pending) => *pending != frame,
+            None => true
+        };
+        if changed {
+            if self.pending.is_some() {
+                let delay = centiseconds(self.now) - centiseconds(self.pending_start);
+                // A frame too short lived to show is dropped, and the next
+                // one takes its place, so the timing still adds up
+                if delay >= MIN_DELAY {
+                    self.write_pending(delay)?;
+                    self.pending_start = self.now;
+                }
+            }
+            self.pending = Some(frame);
+        }
+
+        self.now += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.pending.is_some() {
+            let delay = centiseconds(self.now) - centiseconds(self.pending_start);
+            self.write_pending(delay.max(MIN_DELAY))?;
+        }
+        self.out.write_all(&[0x3b])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_pending(&mut self, delay: u64) -> io::Result<()> {
+        let frame = self.pending.clone().unwrap();
+
+        // the delay field only holds 655 seconds, so a very long still
+        // screen goes out as several frames
+        let mut delay = delay;
+        while delay > 0 {
+            let this_delay = delay.min(u16::MAX as u64);
+            self.write_frame(&frame, this_delay as u16)?;
+            delay -= this_delay;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &Frame, delay: u16) -> io::Result<()> {
+        // Graphic control extension, for the delay
+        self.out.write_all(&[0x21, 0xf9, 4, 0x04])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0, 0])?;
+
+        let width = WIDTH * self.scale;
+        let height = HEIGHT * self.scale;
+        self.out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.out.write_all(&(width as u16).to_le_bytes())?;
+        self.out.write_all(&(height as u16).to_le_bytes())?;
+        // frames shown in another palette carry their own colour table
+        if frame.colors != self.colors {
+            self.out.write_all(&[0x80])?;
+            write_color_table(&mut self.out, &frame.colors)?;
+        } else {
+            self.out.write_all(&[0])?;
+        }
+
+        let mut indices = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let lit = frame.gfx[(y / self.scale) * WIDTH + x / self.scale] != 0;
+                indices.push(if lit { 1 } else { 0 });
+            }
+        }
+
+        // 2 is the smallest code size GIF allows, even for 2 colours
+        self.out.write_all(&[2])?;
+        for block in lzw_encode(&indices, 2).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0])
+    }
+}
+
+// GIF delays are in hundredths of a second, which 60hz frames don't
+// fit, so they're rounded from the start of the recording to not drift
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + 30) / 60
+}
+
+fn write_color_table<W: Write>(out: &mut W, colors: &[Color; 2]) -> io::Result<()> {
+    for color in colors.iter() {
+        out.write_all(&[color.r, color.g, color.b])?;
+    }
+    Ok(())
+}
+
+// Packs variable width codes into bytes, least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u8
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// The variable code size LZW that GIF uses for its image data
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    let mut bits = BitWriter { bytes: Vec::new(), buffer: 0, count: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end + 1;
+
+    bits.write(clear, code_size);
+
+    let mut prefix: Option<u16> = None;
+    for &index in indices {
+        prefix = Some(match prefix {
+            None => index as u16,
+            Some(prefix) => match table.get(&(prefix, index)) {
+                Some(&code) => code,
+                None => {
+                    bits.write(prefix, code_size);
+                    if next_code == 4096 {
+                        // codes only go up to 12 bits, so start again
+                        bits.write(clear, code_size);
+                        table.clear();
+                        code_size = min_code_size + 1;
+                        next_code = end + 1;
+                    } else {
+                        table.insert((prefix, index), next_code);
+                        if next_code == 1 << code_size {
+                            code_size += 1;
+                        }
+                        next_code += 1;
+                    }
+                    index as u16
+                }
+            }
+        });
+    }
+
+    if let Some(prefix) = prefix {
+        bits.write(prefix, code_size);
+    }
+    bits.write(end, code_size);
+    bits.finish()
+}
+
+#[cfg(test)]
+mod gif_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A plain GIF LZW decoder, to check the encoder against
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let mut output = Vec::new();
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut buffer, mut count, mut position) = (0u32, 0u8, 0);
+
+        loop {
+            while count < code_size {
+                buffer |= (data[position] as u32) << count;
+                position += 1;
+                count += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as u16;
+            buffer >>= code_size;
+            count -= code_size;
+
+            if code == clear {
+                table = (0..clear).map(|i| vec![i as u8]).collect();
+                table.push(vec![]);
+                table.push(vec![]);
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+
+            let entry = if (code as usize) < table.len() {
+                table[code as usize].clone()
+            } else {
+                let mut entry = previous.clone().unwrap();
+                entry.push(entry[0]);
+                entry
+            };
+            output.extend(&entry);
+
+            if let Some(mut previous) = previous {
+                previous.push(entry[0]);
+                table.push(previous);
+                if table.len() == 1 << code_size && code_size < 12 {
+                    code_size += 1;
+                }
+            }
+            previous = Some(entry);
+        }
+    }
+
+    fn test_palette() -> Palette {
+        Palette::new("test", Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))
+    }
+
+    // The delays of every frame in a recording
+    fn delays(bytes: &[u8]) -> Vec<u16> {
+        bytes.windows(4).enumerate()
+            .filter(|&(_, window)| window == [0x21, 0xf9, 4, 0x04])
+            .map(|(offset, _)| u16::from_le_bytes([bytes[offset + 4], bytes[offset + 5]]))
+            .collect()
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let simple = vec![0, 0, 1, 1, 0, 1, 0, 0, 0, 0, 1];
+        assert_eq!(lzw_decode(&lzw_encode(&simple, 2), 2), simple);
+
+        // long enough to fill the table and need a clear code
+        let mut state = 12345u32;
+        let noisy: Vec<u8> = (0..20000).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((state >> 16) & 1) as u8
+        }).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&noisy, 2), 2), noisy);
+    }
+
+    #[test]
+    fn header_is_right() {
+        let recorder = GifRecorder::new(Cursor::new(Vec::new()), &test_palette(), 4).unwrap();
+        let bytes = recorder.finish().unwrap().into_inner();
+
+        assert_eq!(&bytes[0..6], b"GIF89a");
+        assert_eq!(&bytes[6..10], &[0, 1, 128, 0]); // 256x128
+        assert_eq!(&bytes[13..19], &[0, 0, 0, 255, 255, 255]);
+        assert_eq!(bytes.last(), Some(&0x3b));
+    }
+
+    #[test]
+    fn identical_frames_are_merged() {
+        let palette = test_palette();
+        let mut recorder = GifRecorder::new(Cursor::new(Vec::new()), &palette, 1).unwrap();
+        let blank = [0u8; WIDTH * HEIGHT];
+        let mut dot = blank;
+        dot[0] = 1;
+
+        for _ in 0..60 {
+            recorder.frame(&blank, &palette).unwrap();
+        }
+        for _ in 0..30 {
+            recorder.frame(&dot, &palette).unwrap();
+        }
+        let bytes = recorder.finish().unwrap().into_inner();
+        assert_eq!(delays(&bytes), vec![100, 50]);
+    }
+
+    #[test]
+    fn delays_add_up_to_60hz() {
+        let palette = test_palette();
+        let mut recorder = GifRecorder::new(Cursor::new(Vec::new()), &palette, 1).unwrap();
+        let blank = [0u8; WIDTH * HEIGHT];
+        let mut dot = blank;
+        dot[0] = 1;
+
+        // a different picture every frame, as with flickering sprites
+        for frame in 0..60 {
+            recorder.frame(if frame % 2 == 0 { &blank } else { &dot }, &palette).unwrap();
+        }
+        let bytes = recorder.finish().unwrap().into_inner();
+        let delays = delays(&bytes);
+        assert!(delays.iter().all(|&delay| delay >= 2));
+        assert_eq!(delays.iter().map(|&delay| delay as u32).sum::<u32>(), 100);
+    }
+}
@@ -0,0 +1,99 @@
+use keymap::{HostKey, KeyMap};
+use romdb::RomInfo;
+
+// Characters across the help box, before the goal wraps
+const WIDTH: usize = 44;
+// Host keys go in a column this wide, with what they do beside them
+const KEYS_WIDTH: usize = 14;
+
+// What a game is about and how to play it, with the CHIP-8 keys it
+// uses written as whichever host keys press them. `name` says what's
+// printed on a host key.
+pub fn help_lines<F>(rom: &RomInfo, keymap: &KeyMap, name: F) -> Vec<String>
+    where F: Fn(HostKey) -> String {
+    let mut lines = vec![format!("{} by {}", rom.title, rom.author), String::new()];
+    lines.extend(wrap(rom.goal, WIDTH));
+    lines.push(String::new());
+
+    if rom.keys.is_empty() {
+        lines.push("No keys to press".to_string());
+    }
+    for hint in rom.keys.iter() {
+        let keys: Vec<String> = hint.keys.iter().map(|&key| {
+            let hosts: Vec<String> = keymap.host_keys_for(key).into_iter().map(&name).collect();
+            // a key the mapping leaves out can't be pressed, but say which it is
+            if hosts.is_empty() { format!("({:X})", key) } else { hosts.join("/") }
+        }).collect();
+        let keys = keys.join(" ");
+        if keys.len() < KEYS_WIDTH {
+            lines.push(format!("{:<width$}{}", keys, hint.action, width = KEYS_WIDTH));
+        } else {
+            lines.push(keys);
+            lines.push(format!("{:<width$}{}", "", hint.action, width = KEYS_WIDTH));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("F1 to close".to_string());
+    lines
+}
+
+// Breaks text into lines of at most `width` characters, between words
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod help_tests {
+    use super::*;
+    use romdb::ROMS;
+
+    // stands in for SDL's name tables
+    fn test_name(host: HostKey) -> String {
+        match host {
+            HostKey::Scan(scancode) => format!("{:?}", scancode).replace("Num", ""),
+            HostKey::Key(keycode) => format!("{:?}", keycode)
+        }
+    }
+
+    #[test]
+    fn keys_are_shown_as_the_host_keys_that_press_them() {
+        let pong = ROMS.iter().find(|rom| rom.title == "Pong").unwrap();
+        let lines = help_lines(pong, &KeyMap::preset("qwerty").unwrap(), test_name);
+        assert_eq!(lines[0], "Pong by Paul Vervalin");
+        assert!(lines.contains(&"1 Q           left paddle up, down".to_string()), "{:?}", lines);
+
+        // and ones nothing presses are still named
+        let lines = help_lines(pong, &KeyMap::new(), test_name);
+        assert!(lines.contains(&"(1) (4)       left paddle up, down".to_string()), "{:?}", lines);
+    }
+
+    #[test]
+    fn every_bundled_game_has_help() {
+        for rom in ROMS.iter() {
+            let lines = help_lines(rom, &KeyMap::preset("hex").unwrap(), test_name);
+            assert!(lines.iter().all(|line| line.len() <= WIDTH), "{:?}", lines);
+        }
+    }
+
+    #[test]
+    fn long_text_wraps_between_words() {
+        assert_eq!(wrap("one two three", 7), vec!["one two", "three"]);
+        assert_eq!(wrap("", 7), Vec::<String>::new());
+    }
+}
@@ -1,119 +1,147 @@
+use std::fs;
+
+// The hex digits as the COSMAC VIP drew them, 4 pixels wide and 5 high
+const VIP_FONT: [u8; 80] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
+    0xf0, 0x10, 0x70, 0x10, 0xf0, // 3
+    0xa0, 0xa0, 0xf0, 0x20, 0x20, // 4
+    0xf0, 0x80, 0xf0, 0x10, 0xf0, // 5
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, // 6
+    0xf0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, // 8
+    0xf0, 0x90, 0xf0, 0x10, 0xf0, // 9
+    0xf0, 0x90, 0xf0, 0x90, 0x90, // A
+    0xf0, 0x50, 0x70, 0x50, 0xf0, // B
+    0xf0, 0x80, 0x80, 0x80, 0xf0, // C
+    0xf0, 0x50, 0x50, 0x50, 0xf0, // D
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
+    0xf0, 0x80, 0xf0, 0x80, 0x80  // F
+];
+
+// CHIP-48's, which SCHIP and Octo kept, and most interpreters since
+const CHIP48_FONT: [u8; 80] = [
+    0xf0, 0x90, 0x90, 0x90, 0xf0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xf0, 0x10, 0xf0, 0x80, 0xf0, // 2
+    0xf0, 0x10, 0xf0, 0x10, 0xf0, // 3
+    0x90, 0x90, 0xf0, 0x10, 0x10, // 4
+    0xf0, 0x80, 0xf0, 0x10, 0xf0, // 5
+    0xf0, 0x80, 0xf0, 0x90, 0xf0, // 6
+    0xf0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xf0, 0x90, 0xf0, 0x90, 0xf0, // 8
+    0xf0, 0x90, 0xf0, 0x10, 0xf0, // 9
+    0xf0, 0x90, 0xf0, 0x90, 0x90, // A
+    0xe0, 0x90, 0xe0, 0x90, 0xe0, // B
+    0xf0, 0x80, 0x80, 0x80, 0xf0, // C
+    0xe0, 0x90, 0x90, 0x90, 0xe0, // D
+    0xf0, 0x80, 0xf0, 0x80, 0xf0, // E
+    0xf0, 0x80, 0xf0, 0x80, 0x80  // F
+];
+
+// SCHIP's big digits for FX30, 8 pixels wide and 10 high. There are
+// only ten, as SCHIP 1.1 had no big A to F.
+const SCHIP_BIG_FONT: [u8; 100] = [
+    0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, // 1
+    0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff, // 2
+    0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c, // 3
+    0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c, // 5
+    0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c, // 6
+    0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c, // 8
+    0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c  // 9
+];
+
+// Octo's big font, squarer than SCHIP's and with all sixteen
+const OCTO_BIG_FONT: [u8; 160] = [
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff, // 1
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // 2
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 3
+    0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 5
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 6
+    0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18, // 7
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 8
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 9
+    0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // A
+    0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, // B
+    0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c, // C
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // D
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // E
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0  // F
+];
+
+// Bytes in each of the small and big glyphs
+pub const SMALL_GLYPH_SIZE: usize = 5;
+pub const BIG_GLYPH_SIZE: usize = 10;
+
+// Names of the fonts that come with the emulator
+pub const FONTS: [&str; 4] = ["vip", "chip48", "schip", "octo"];
+
+// The hex digits a program gets from FX29, and FX30 if the font has
+// big ones. In memory the big digits follow straight after the small.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Font {
+    pub small: Vec<u8>,
+    // Ten digits, sixteen, or none at all
+    pub big: Vec<u8>
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font::builtin("chip48").unwrap()
+    }
+}
+
+impl Font {
+    pub fn builtin(name: &str) -> Option<Font> {
+        let (small, big): (&[u8], &[u8]) = match name {
+            "vip" => (&VIP_FONT, &[]),
+            "chip48" => (&CHIP48_FONT, &[]),
+            "schip" => (&CHIP48_FONT, &SCHIP_BIG_FONT),
+            "octo" => (&CHIP48_FONT, &OCTO_BIG_FONT),
+            _ => return None
+        };
+        Some(Font { small: small.to_vec(), big: big.to_vec() })
+    }
+
+    // One of the built-in fonts, or a font file
+    pub fn load(name_or_path: &str) -> Result<Font, String> {
+        if let Some(font) = Font::builtin(name_or_path) {
+            return Ok(font);
+        }
+        let bytes = fs::read(name_or_path).map_err(|e| {
+            format!("'{}' is not a font ({}) or a readable file: {}", name_or_path, FONTS.join(", "), e)
+        })?;
+        Font::from_bytes(&bytes).map_err(|e| format!("'{}' {}", name_or_path, e))
+    }
+
+    // Font files are the glyphs as they go in memory: the 16 small ones,
+    // then 10 or 16 big ones if there are any
+    pub fn from_bytes(bytes: &[u8]) -> Result<Font, String> {
+        let small_size = 16 * SMALL_GLYPH_SIZE;
+        let big_size = bytes.len().saturating_sub(small_size);
+        if bytes.len() < small_size || ![0, 10 * BIG_GLYPH_SIZE, 16 * BIG_GLYPH_SIZE].contains(&big_size) {
+            return Err(format!("is {} bytes, but fonts are {}, {} or {}", bytes.len(),
+                small_size, small_size + 10 * BIG_GLYPH_SIZE, small_size + 16 * BIG_GLYPH_SIZE));
+        }
+        Ok(Font { small: bytes[..small_size].to_vec(), big: bytes[small_size..].to_vec() })
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.small.clone();
+        bytes.extend_from_slice(&self.big);
+        bytes
+    }
+}
+
+// The usual small font, which is also drawn on the keypad
 pub fn get_hex_font() -> Vec<u8> {
-    let mut result = Vec::<u8>::with_capacity(16);
-
-    // 0
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0x90);
-    result.push(0x90);
-    result.push(0xf0);
-
-    // 1
-    result.push(0x20);
-    result.push(0x60);
-    result.push(0x20);
-    result.push(0x20);
-    result.push(0x70);
-
-    // 2
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-
-    // 3
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0xf0);
-
-    // 4
-    result.push(0x90);
-    result.push(0x90);
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0x10);
-
-    // 5
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0xf0);
-
-    // 6
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0xf0);
-
-    // 7
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0x20);
-    result.push(0x40);
-    result.push(0x40);
-
-    // 8
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0xf0);
-
-    // 9
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0xf0);
-    result.push(0x10);
-    result.push(0xf0);
-
-    // A
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0xf0);
-    result.push(0x90);
-    result.push(0x90);
-
-    // B
-    result.push(0xe0);
-    result.push(0x90);
-    result.push(0xe0);
-    result.push(0x90);
-    result.push(0xe0);
-
-    // C
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0x80);
-    result.push(0x80);
-    result.push(0xf0);
-
-    // D
-    result.push(0xe0);
-    result.push(0x90);
-    result.push(0x90);
-    result.push(0x90);
-    result.push(0xe0);
-
-    // E
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-
-    // F
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0xf0);
-    result.push(0x80);
-    result.push(0x80);
-
-    result
+    CHIP48_FONT.to_vec()
 }
 
 #[cfg(test)]
@@ -135,4 +163,27 @@ mod font_tests {
             assert!(font[i] > 0x00);
         }
     }
+
+    #[test]
+    fn every_builtin_font_is_whole() {
+        for name in FONTS.iter() {
+            let font = Font::builtin(name).unwrap();
+            assert_eq!(font.small.len(), 16 * SMALL_GLYPH_SIZE, "{}", name);
+            assert!(font.small.iter().all(|row| row & 0x0f == 0), "{}", name);
+            assert!([0, 100, 160].contains(&font.big.len()), "{}", name);
+        }
+        assert_eq!(Font::default().small, get_hex_font());
+        // the VIP's 7 is straight down, CHIP-48's leans
+        assert_eq!(Font::builtin("vip").unwrap().small[35..40], [0xf0, 0x10, 0x10, 0x10, 0x10]);
+        assert_eq!(Font::builtin("schip").unwrap().big.len(), 10 * BIG_GLYPH_SIZE);
+    }
+
+    #[test]
+    fn fonts_load_from_files() {
+        let schip = Font::builtin("schip").unwrap();
+        assert_eq!(Font::from_bytes(&schip.bytes()), Ok(schip));
+        assert_eq!(Font::from_bytes(&[0xf0; 80]).unwrap().big, vec![]);
+        assert_eq!(Font::from_bytes(&[0xf0; 81]).unwrap_err(), "is 81 bytes, but fonts are 80, 180 or 240");
+        assert!(Font::load("no/such/font").unwrap_err().contains("vip, chip48, schip, octo"));
+    }
 }
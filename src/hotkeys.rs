@@ -0,0 +1,61 @@
+use sdl2::keyboard::Keycode;
+
+// Emulator controls, kept on the function keys (and Tab) so
+// they never collide with keys bound to the CHIP-8 keypad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    Reset,
+    Pause,
+    FrameAdvance,
+    SlowMotion,
+    FastForward,
+    CyclePalette,
+    CyclePersistence,
+    CycleFilter,
+    Mute,
+    CycleOverlay,
+    Record,
+    Screenshot,
+    Help
+}
+
+pub fn hotkey_for(key: Keycode) -> Option<Hotkey> {
+    match key {
+        Keycode::F1 => Some(Hotkey::Help),
+        Keycode::F2 => Some(Hotkey::CyclePalette),
+        Keycode::F3 => Some(Hotkey::CyclePersistence),
+        Keycode::F4 => Some(Hotkey::CycleFilter),
+        Keycode::F5 => Some(Hotkey::Reset),
+        Keycode::F6 => Some(Hotkey::Pause),
+        Keycode::F7 => Some(Hotkey::FrameAdvance),
+        Keycode::F8 => Some(Hotkey::SlowMotion),
+        Keycode::F9 => Some(Hotkey::Mute),
+        Keycode::F10 => Some(Hotkey::CycleOverlay),
+        Keycode::F11 => Some(Hotkey::Record),
+        Keycode::F12 => Some(Hotkey::Screenshot),
+        // held down rather than pressed, like a tape deck
+        Keycode::Tab => Some(Hotkey::FastForward),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod hotkey_tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_are_found() {
+        assert_eq!(hotkey_for(Keycode::F1), Some(Hotkey::Help));
+        assert_eq!(hotkey_for(Keycode::F2), Some(Hotkey::CyclePalette));
+        assert_eq!(hotkey_for(Keycode::F3), Some(Hotkey::CyclePersistence));
+        assert_eq!(hotkey_for(Keycode::F11), Some(Hotkey::Record));
+        assert_eq!(hotkey_for(Keycode::F12), Some(Hotkey::Screenshot));
+        assert_eq!(hotkey_for(Keycode::Tab), Some(Hotkey::FastForward));
+    }
+
+    #[test]
+    fn keypad_keys_are_not_hotkeys() {
+        assert_eq!(hotkey_for(Keycode::Num1), None);
+        assert_eq!(hotkey_for(Keycode::X), None);
+    }
+}
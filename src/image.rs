@@ -0,0 +1,130 @@
+use sdl2::pixels::Color;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+// A plain RGB picture in main memory, so that it can be
+// post-processed and saved without going through the GPU
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    // Row-major, top row first
+    pub pixels: Vec<Color>
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, fill: Color) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![fill; (width * height) as usize]
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    // Packed 3 bytes per pixel, R then G then B, for SDL's RGB24 textures
+    pub fn to_rgb24(&self) -> Vec<u8> {
+        let mut result = Vec::<u8>::with_capacity(self.pixels.len() * 3);
+        for pixel in &self.pixels {
+            result.push(pixel.r);
+            result.push(pixel.g);
+            result.push(pixel.b);
+        }
+        result
+    }
+
+    pub fn save_bmp(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        self.write_bmp(&mut file)
+    }
+
+    // Uncompressed 24-bit BMP, which everything can open
+    pub fn write_bmp<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        // rows are padded out to a multiple of 4 bytes
+        let row_size = (self.width * 3 + 3) & !3;
+        let data_size = row_size * self.height;
+        let header_size = 14 + 40;
+
+        // BITMAPFILEHEADER
+        out.write_all(b"BM")?;
+        out.write_all(&u32_le(header_size + data_size))?;
+        out.write_all(&[0u8; 4])?; // reserved
+        out.write_all(&u32_le(header_size))?;
+
+        // BITMAPINFOHEADER
+        out.write_all(&u32_le(40))?;
+        out.write_all(&u32_le(self.width))?;
+        out.write_all(&u32_le(self.height))?;
+        out.write_all(&[1, 0])?; // colour planes
+        out.write_all(&[24, 0])?; // bits per pixel
+        out.write_all(&u32_le(0))?; // no compression
+        out.write_all(&u32_le(data_size))?;
+        out.write_all(&u32_le(2835))?; // 72 dpi, in pixels per metre
+        out.write_all(&u32_le(2835))?;
+        out.write_all(&u32_le(0))?; // palette size
+        out.write_all(&u32_le(0))?; // important colours
+
+        // stored bottom row first, as BGR
+        let padding = vec![0u8; (row_size - self.width * 3) as usize];
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let pixel = self.get(x, y);
+                out.write_all(&[pixel.b, pixel.g, pixel.r])?;
+            }
+            out.write_all(&padding)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn u32_le(value: u32) -> [u8; 4] {
+    [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+}
+
+#[cfg(test)]
+mod image_tests {
+    use super::*;
+
+    #[test]
+    fn get_and_set_work() {
+        let mut image = Image::new(3, 2, Color::RGB(0, 0, 0));
+        image.set(2, 1, Color::RGB(1, 2, 3));
+        assert_eq!(image.get(2, 1), Color::RGB(1, 2, 3));
+        assert_eq!(image.pixels[5], Color::RGB(1, 2, 3));
+        assert_eq!(image.get(0, 0), Color::RGB(0, 0, 0));
+    }
+
+    #[test]
+    fn to_rgb24_works() {
+        let mut image = Image::new(2, 1, Color::RGB(1, 2, 3));
+        image.set(1, 0, Color::RGB(4, 5, 6));
+        assert_eq!(image.to_rgb24(), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn bmp_layout_is_right() {
+        let mut image = Image::new(1, 2, Color::RGB(1, 2, 3));
+        image.set(0, 1, Color::RGB(4, 5, 6));
+
+        let mut bmp = Vec::<u8>::new();
+        image.write_bmp(&mut bmp).unwrap();
+
+        // 54 byte header, then two rows of 3 bytes padded to 4
+        assert_eq!(bmp.len(), 54 + 8);
+        assert_eq!(&bmp[0..2], b"BM");
+        assert_eq!(bmp[2], 62); // file size
+        assert_eq!(bmp[10], 54); // pixel data offset
+
+        // bottom row first, in BGR order
+        assert_eq!(&bmp[54..62], &[6, 5, 4, 0, 3, 2, 1, 0]);
+    }
+}
@@ -0,0 +1,343 @@
+use sdl2::controller::Button;
+use sdl2::keyboard::{Keycode, Scancode};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::prelude::*;
+
+// A key on the host keyboard. Scancodes name a physical position and
+// so work on any layout; keycodes name whatever is printed on the key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HostKey {
+    Scan(Scancode),
+    Key(Keycode)
+}
+
+// Which host keys press which of the 16 CHIP-8 keys.
+// Any number of host keys can press the same CHIP-8 key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyMap {
+    bindings: HashMap<HostKey, u8>
+}
+
+pub const PRESETS: [&str; 3] = ["qwerty", "keypad", "hex"];
+
+impl KeyMap {
+    pub fn new() -> KeyMap {
+        KeyMap { bindings: HashMap::new() }
+    }
+
+    pub fn bind(&mut self, host: HostKey, key: u8) {
+        self.bindings.insert(host, key);
+    }
+
+    // Every host key that presses a CHIP-8 key, positions before labels
+    pub fn host_keys_for(&self, key: u8) -> Vec<HostKey> {
+        let mut hosts: Vec<HostKey> = self.bindings.iter()
+            .filter(|&(_, bound)| *bound == key)
+            .map(|(host, _)| *host)
+            .collect();
+        hosts.sort_by_key(|host| match *host {
+            HostKey::Scan(scancode) => (0, scancode as i32),
+            HostKey::Key(keycode) => (1, keycode as i32)
+        });
+        hosts
+    }
+
+    // Physical position wins over the printed label
+    pub fn lookup(&self, scancode: Option<Scancode>, keycode: Option<Keycode>) -> Option<u8> {
+        let by_position = scancode.and_then(|s| self.bindings.get(&HostKey::Scan(s)));
+        let by_label = keycode.and_then(|k| self.bindings.get(&HostKey::Key(k)));
+        by_position.or(by_label).cloned()
+    }
+
+    pub fn preset(name: &str) -> Option<KeyMap> {
+        let mut map = KeyMap::new();
+        match name {
+            "qwerty" => {
+                // The left hand block of a QWERTY keyboard, mirroring the
+                // COSMAC VIP keypad:
+                //  1 2 3 4      1 2 3 C
+                //  Q W E R  =>  4 5 6 D
+                //  A S D F      7 8 9 E
+                //  Z X C V      A 0 B F
+                let layout = [
+                    (Scancode::Num1, 0x1), (Scancode::Num2, 0x2), (Scancode::Num3, 0x3), (Scancode::Num4, 0xc),
+                    (Scancode::Q, 0x4), (Scancode::W, 0x5), (Scancode::E, 0x6), (Scancode::R, 0xd),
+                    (Scancode::A, 0x7), (Scancode::S, 0x8), (Scancode::D, 0x9), (Scancode::F, 0xe),
+                    (Scancode::Z, 0xa), (Scancode::X, 0x0), (Scancode::C, 0xb), (Scancode::V, 0xf)
+                ];
+                for &(scancode, key) in layout.iter() {
+                    map.bind(HostKey::Scan(scancode), key);
+                }
+            },
+            "keypad" => {
+                // The numeric keypad, laid out in the same positions as the
+                // COSMAC VIP keypad, with A, C-F on the operator keys
+                //  7 8 9 -      1 2 3 C
+                //  4 5 6 +  =>  4 5 6 D
+                //  1 2 3 ent    7 8 9 E
+                //  0 . / *      0 B A F
+                let layout = [
+                    (Scancode::Kp7, 0x1), (Scancode::Kp8, 0x2), (Scancode::Kp9, 0x3), (Scancode::KpMinus, 0xc),
+                    (Scancode::Kp4, 0x4), (Scancode::Kp5, 0x5), (Scancode::Kp6, 0x6), (Scancode::KpPlus, 0xd),
+                    (Scancode::Kp1, 0x7), (Scancode::Kp2, 0x8), (Scancode::Kp3, 0x9), (Scancode::KpEnter, 0xe),
+                    (Scancode::Kp0, 0x0), (Scancode::KpPeriod, 0xb), (Scancode::KpDivide, 0xa), (Scancode::KpMultiply, 0xf)
+                ];
+                for &(scancode, key) in layout.iter() {
+                    map.bind(HostKey::Scan(scancode), key);
+                }
+            },
+            "hex" => {
+                // Every key is the hex digit printed on it: the digit row by
+                // position, and A-F by label so they work on any layout
+                let digits = [
+                    Scancode::Num0, Scancode::Num1, Scancode::Num2, Scancode::Num3, Scancode::Num4,
+                    Scancode::Num5, Scancode::Num6, Scancode::Num7, Scancode::Num8, Scancode::Num9
+                ];
+                for (key, scancode) in digits.iter().enumerate() {
+                    map.bind(HostKey::Scan(*scancode), key as u8);
+                }
+
+                let letters = [Keycode::A, Keycode::B, Keycode::C, Keycode::D, Keycode::E, Keycode::F];
+                for (offset, keycode) in letters.iter().enumerate() {
+                    map.bind(HostKey::Key(*keycode), 0xa + offset as u8);
+                }
+            },
+            _ => return None
+        }
+        Some(map)
+    }
+
+    // Either the name of a preset, or the path to a key mapping file
+    pub fn load(preset_or_path: &str) -> Result<KeyMap, String> {
+        if let Some(map) = KeyMap::preset(preset_or_path) {
+            return Ok(map);
+        }
+
+        let mut file = File::open(preset_or_path).map_err(|e| {
+            format!("'{}' is not a key preset ({}) or a readable file: {}", preset_or_path, PRESETS.join(", "), e)
+        })?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(|e| format!("Could not read '{}': {}", preset_or_path, e))?;
+
+        KeyMap::parse(&text, host_key_from_name)
+    }
+
+    // Key mapping files look like:
+    //
+    //   # start from a preset (optional)
+    //   preset = qwerty
+    //   # CHIP-8 key = host keys, separated by commas
+    //   5 = W, Up
+    //   A = key:A
+    //
+    // Host keys are SDL scancode names, or keycode names after "key:"
+    pub fn parse<F>(text: &str, resolve: F) -> Result<KeyMap, String>
+        where F: Fn(&str) -> Option<HostKey> {
+        let mut map = KeyMap::new();
+
+        for_each_binding(text, |name, value| {
+            if name == "preset" {
+                let preset = KeyMap::preset(value).ok_or_else(|| format!("unknown preset '{}'", value))?;
+                map.bindings.extend(preset.bindings);
+                return Ok(());
+            }
+
+            let key = parse_chip8_key(name)?;
+            for host_name in value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                let host = resolve(host_name).ok_or_else(|| format!("unknown host key '{}'", host_name))?;
+                map.bind(host, key);
+            }
+            Ok(())
+        })?;
+
+        Ok(map)
+    }
+}
+
+// Calls back with the two sides of every "name = value" line, skipping
+// blank lines and # comments. Errors come back with the line number.
+pub fn for_each_binding<F>(text: &str, mut handle: F) -> Result<(), String>
+    where F: FnMut(&str, &str) -> Result<(), String> {
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut sides = line.splitn(2, '=');
+        let name = sides.next().unwrap().trim();
+        let result = match sides.next() {
+            Some(value) => handle(name, value.trim()),
+            None => Err(format!("expected 'name = value', got '{}'", line))
+        };
+        result.map_err(|message| format!("line {}: {}", number + 1, message))?;
+    }
+
+    Ok(())
+}
+
+// A single hex digit, 0-F
+pub fn parse_chip8_key(name: &str) -> Result<u8, String> {
+    match u8::from_str_radix(name, 16) {
+        Ok(key) if name.len() == 1 => Ok(key),
+        _ => Err(format!("'{}' is not a CHIP-8 key, expected 0-F", name))
+    }
+}
+
+pub fn host_key_from_name(name: &str) -> Option<HostKey> {
+    match name.strip_prefix("key:") {
+        Some(label) => Keycode::from_name(label).map(HostKey::Key),
+        None => Scancode::from_name(name).map(HostKey::Scan)
+    }
+}
+
+// What's printed on a host key, for showing to the player
+pub fn host_key_name(host: HostKey) -> String {
+    match host {
+        HostKey::Scan(scancode) => scancode.name().to_string(),
+        HostKey::Key(keycode) => keycode.name()
+    }
+}
+
+// Whatever is holding a CHIP-8 key down: a host key, a button on one
+// of the controllers, or the mouse on the on-screen keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeySource {
+    Keyboard(HostKey),
+    Pad(i32, Button),
+    Keypad
+}
+
+impl KeySource {
+    // The key itself, wherever it is, so it's let go of whatever it's labelled
+    pub fn keyboard(scancode: Option<Scancode>, keycode: Option<Keycode>) -> Option<KeySource> {
+        scancode.map(HostKey::Scan).or_else(|| keycode.map(HostKey::Key)).map(KeySource::Keyboard)
+    }
+}
+
+// With several host keys, buttons and the keypad all able to press the
+// same CHIP-8 key, it stays down until the last of them lets go
+#[derive(Clone, Debug, Default)]
+pub struct HeldKeys {
+    held: HashMap<KeySource, u8>
+}
+
+impl HeldKeys {
+    // Whether this presses the key, rather than holding it down harder
+    pub fn press(&mut self, source: KeySource, key: u8) -> bool {
+        let already_down = self.held.values().any(|held| *held == key);
+        self.held.insert(source, key);
+        !already_down
+    }
+
+    // The key to let go of, if nothing else is holding it down
+    pub fn release(&mut self, source: KeySource) -> Option<u8> {
+        let key = self.held.remove(&source)?;
+        if self.held.values().any(|held| *held == key) {
+            None
+        } else {
+            Some(key)
+        }
+    }
+}
+
+#[cfg(test)]
+mod keymap_tests {
+    use super::*;
+
+    // stands in for SDL's name tables
+    fn test_resolve(name: &str) -> Option<HostKey> {
+        match name {
+            "W" => Some(HostKey::Scan(Scancode::W)),
+            "Up" => Some(HostKey::Scan(Scancode::Up)),
+            "key:A" => Some(HostKey::Key(Keycode::A)),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn presets_cover_every_key() {
+        for name in PRESETS.iter() {
+            let map = KeyMap::preset(name).unwrap();
+            for key in 0..16 {
+                assert!(map.bindings.values().any(|k| *k == key), "{} has nothing bound to {:x}", name, key);
+            }
+        }
+        assert!(KeyMap::preset("colemak").is_none());
+    }
+
+    #[test]
+    fn qwerty_preset_matches_the_classic_layout() {
+        let map = KeyMap::preset("qwerty").unwrap();
+        assert_eq!(map.lookup(Some(Scancode::Num4), None), Some(0xc));
+        assert_eq!(map.lookup(Some(Scancode::X), None), Some(0x0));
+        assert_eq!(map.lookup(Some(Scancode::V), None), Some(0xf));
+        assert_eq!(map.lookup(Some(Scancode::P), None), None);
+    }
+
+    #[test]
+    fn host_keys_are_found_for_chip8_keys() {
+        let mut map = KeyMap::preset("qwerty").unwrap();
+        map.bind(HostKey::Key(Keycode::Up), 5);
+        assert_eq!(map.host_keys_for(5), vec![HostKey::Scan(Scancode::W), HostKey::Key(Keycode::Up)]);
+        assert_eq!(map.host_keys_for(0), vec![HostKey::Scan(Scancode::X)]);
+        assert!(KeyMap::new().host_keys_for(5).is_empty());
+    }
+
+    #[test]
+    fn lookup_prefers_scancodes() {
+        let mut map = KeyMap::new();
+        map.bind(HostKey::Scan(Scancode::Q), 4);
+        map.bind(HostKey::Key(Keycode::A), 0xa);
+
+        // on AZERTY the key in the Q position is labelled A
+        assert_eq!(map.lookup(Some(Scancode::Q), Some(Keycode::A)), Some(4));
+        assert_eq!(map.lookup(Some(Scancode::Num9), Some(Keycode::A)), Some(0xa));
+    }
+
+    #[test]
+    fn parse_works() {
+        let text = "# my layout\n5 = W, Up\nA = key:A # by label\n";
+        let map = KeyMap::parse(text, test_resolve).unwrap();
+        assert_eq!(map.lookup(Some(Scancode::W), None), Some(5));
+        assert_eq!(map.lookup(Some(Scancode::Up), None), Some(5));
+        assert_eq!(map.lookup(None, Some(Keycode::A)), Some(0xa));
+        assert_eq!(map.bindings.len(), 3);
+    }
+
+    #[test]
+    fn parse_can_extend_a_preset() {
+        let map = KeyMap::parse("preset = qwerty\n5 = Up", test_resolve).unwrap();
+        assert_eq!(map.lookup(Some(Scancode::W), None), Some(5));
+        assert_eq!(map.lookup(Some(Scancode::Up), None), Some(5));
+        assert_eq!(map.lookup(Some(Scancode::X), None), Some(0));
+    }
+
+    #[test]
+    fn parse_reports_bad_lines() {
+        assert!(KeyMap::parse("G = W", test_resolve).unwrap_err().contains("line 1"));
+        assert!(KeyMap::parse("\n5 = Nope", test_resolve).unwrap_err().contains("line 2"));
+        assert!(KeyMap::parse("5 W", test_resolve).is_err());
+        assert!(KeyMap::parse("preset = dvorak", test_resolve).is_err());
+    }
+
+    #[test]
+    fn keys_stay_down_until_the_last_source_lets_go() {
+        let mut held = HeldKeys::default();
+        let w = KeySource::Keyboard(HostKey::Scan(Scancode::W));
+        let up = KeySource::Keyboard(HostKey::Key(Keycode::Up));
+        let pad = KeySource::Pad(0, Button::DPadUp);
+
+        assert!(held.press(w, 5));
+        assert!(!held.press(up, 5));
+        assert!(!held.press(pad, 5));
+        assert_eq!(held.release(w), None);
+        assert_eq!(held.release(pad), None);
+        assert_eq!(held.release(up), Some(5));
+
+        // letting go of something that wasn't pressed does nothing
+        assert_eq!(held.release(KeySource::Keypad), None);
+        assert!(held.press(KeySource::Keypad, 5));
+    }
+}
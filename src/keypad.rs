@@ -0,0 +1,144 @@
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use hexfont::get_hex_font;
+use palette::Palette;
+
+// The keys as they sit on the COSMAC VIP keypad, row by row
+const KEYPAD_LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xc,
+    0x4, 0x5, 0x6, 0xd,
+    0x7, 0x8, 0x9, 0xe,
+    0xa, 0x0, 0xb, 0xf
+];
+
+const KEY_SIZE: u32 = 48;
+const KEY_GAP: u32 = 8;
+// Each pixel of the hex font glyphs becomes a square this big
+const LABEL_SCALE: u32 = 4;
+
+// Width and height of the whole keypad
+pub const KEYPAD_SIZE: u32 = KEY_SIZE * 4 + KEY_GAP * 5;
+
+// A 4x4 hex keypad drawn in the window, which shows the keys being held
+// down and can be clicked (or touched) to press them
+pub struct Keypad {
+    area: Rect,
+    // The key under the mouse button, if it is down
+    clicked: Option<u8>
+}
+
+impl Keypad {
+    pub fn new(area: Rect) -> Keypad {
+        Keypad {
+            area,
+            clicked: None
+        }
+    }
+
+    fn key_rect(&self, index: usize) -> Rect {
+        let column = (index % 4) as u32;
+        let row = (index / 4) as u32;
+        Rect::new(
+            self.area.x() + (KEY_GAP + column * (KEY_SIZE + KEY_GAP)) as i32,
+            self.area.y() + (KEY_GAP + row * (KEY_SIZE + KEY_GAP)) as i32,
+            KEY_SIZE, KEY_SIZE)
+    }
+
+    pub fn key_at(&self, x: i32, y: i32) -> Option<u8> {
+        (0..16).find(|&index| self.key_rect(index).contains_point((x, y))).map(|index| KEYPAD_LAYOUT[index])
+    }
+
+    // Returns the key to press, if the click landed on one
+    pub fn mouse_down(&mut self, x: i32, y: i32) -> Option<u8> {
+        self.clicked = self.key_at(x, y);
+        self.clicked
+    }
+
+    // Returns the key to release, if one was clicked
+    pub fn mouse_up(&mut self) -> Option<u8> {
+        self.clicked.take()
+    }
+
+    pub fn draw<T: RenderTarget>(&self, keys: &[bool; 16], palette: &Palette, canvas: &mut Canvas<T>) {
+        let font = get_hex_font();
+
+        for (index, key) in KEYPAD_LAYOUT.iter().enumerate() {
+            let rect = self.key_rect(index);
+            let held = keys[*key as usize];
+
+            // held keys light up, like the pixels of the display
+            let (face, label) = if held {
+                (palette.foreground, palette.background)
+            } else {
+                (palette.shade(0.2), palette.foreground)
+            };
+
+            canvas.set_draw_color(face);
+            canvas.fill_rect(rect).unwrap();
+
+            // 4x5 glyph from the CHIP-8 font, centred on the key
+            let label_x = rect.x() + ((KEY_SIZE - 4 * LABEL_SCALE) / 2) as i32;
+            let label_y = rect.y() + ((KEY_SIZE - 5 * LABEL_SCALE) / 2) as i32;
+            canvas.set_draw_color(label);
+            for row in 0..5 {
+                let bits = font[*key as usize * 5 + row];
+                for column in 0..4 {
+                    if bits & (0x80 >> column) != 0 {
+                        let pixel = Rect::new(
+                            label_x + (column * LABEL_SCALE) as i32,
+                            label_y + (row as u32 * LABEL_SCALE) as i32,
+                            LABEL_SCALE, LABEL_SCALE);
+                        canvas.fill_rect(pixel).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod keypad_tests {
+    use super::*;
+
+    fn test_keypad() -> Keypad {
+        Keypad::new(Rect::new(100, 50, KEYPAD_SIZE, KEYPAD_SIZE))
+    }
+
+    #[test]
+    fn layout_has_every_key_once() {
+        let mut seen = [false; 16];
+        for key in KEYPAD_LAYOUT.iter() {
+            assert!(!seen[*key as usize]);
+            seen[*key as usize] = true;
+        }
+    }
+
+    #[test]
+    fn key_at_works() {
+        let keypad = test_keypad();
+        // top left key is 1, bottom right is F
+        assert_eq!(keypad.key_at(100 + 10, 50 + 10), Some(0x1));
+        assert_eq!(keypad.key_at(100 + KEYPAD_SIZE as i32 - 10, 50 + KEYPAD_SIZE as i32 - 10), Some(0xf));
+        // second row, second column
+        assert_eq!(keypad.key_at(100 + 70, 50 + 70), Some(0x5));
+    }
+
+    #[test]
+    fn gaps_and_outside_are_not_keys() {
+        let keypad = test_keypad();
+        assert_eq!(keypad.key_at(100 + 2, 50 + 2), None); // in the border
+        assert_eq!(keypad.key_at(100 + 58, 50 + 20), None); // between 1 and 2
+        assert_eq!(keypad.key_at(10, 10), None);
+    }
+
+    #[test]
+    fn clicks_release_the_key_they_pressed() {
+        let mut keypad = test_keypad();
+        assert_eq!(keypad.mouse_down(100 + 70, 50 + 70), Some(0x5));
+        assert_eq!(keypad.mouse_up(), Some(0x5));
+        assert_eq!(keypad.mouse_up(), None);
+
+        assert_eq!(keypad.mouse_down(10, 10), None);
+        assert_eq!(keypad.mouse_up(), None);
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+// Why a program couldn't be loaded
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    NotFound(String),
+    Empty,
+    // sizes in bytes
+    TooLarge { size: usize, room: usize },
+    Unreadable(String)
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::NotFound(ref path) => write!(f, "there's no file called '{}'", path),
+            LoadError::Empty => write!(f, "the ROM is empty"),
+            LoadError::TooLarge { size, room } => write!(f, "the ROM is {} bytes, but there's only room for {}", size, room),
+            LoadError::Unreadable(ref message) => write!(f, "{}", message)
+        }
+    }
+}
+
+// A lone "-" for the ROM reads it from standard input, so there's no
+// file to keep things next to, or to watch
+pub fn is_stdin(path: &str) -> bool {
+    path == "-"
+}
+
+// Reads a whole program from a file, or from standard input for "-"
+pub fn read_program(path: &str) -> Result<Vec<u8>, LoadError> {
+    if is_stdin(path) {
+        return read_from(io::stdin());
+    }
+    match File::open(path) {
+        Ok(file) => read_from(file),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Err(LoadError::NotFound(path.to_string())),
+        Err(e) => Err(LoadError::Unreadable(e.to_string()))
+    }
+}
+
+pub fn read_from<R: Read>(mut reader: R) -> Result<Vec<u8>, LoadError> {
+    let mut program = Vec::new();
+    reader.read_to_end(&mut program).map_err(|e| LoadError::Unreadable(e.to_string()))?;
+    Ok(program)
+}
+
+// Whether a program fits in `room` bytes, and has anything in it at all
+pub fn check_fits(program: &[u8], room: usize) -> Result<(), LoadError> {
+    if program.is_empty() {
+        return Err(LoadError::Empty);
+    }
+    if program.len() > room {
+        return Err(LoadError::TooLarge { size: program.len(), room });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use super::*;
+
+    #[test]
+    fn programs_are_read_from_files_and_readers() {
+        assert_eq!(read_program("roms/c8games/PONG").unwrap().len(), 246);
+        assert_eq!(read_program("no/such/ROM"), Err(LoadError::NotFound("no/such/ROM".to_string())));
+        assert_eq!(read_from(&[0x12u8, 0x00][..]), Ok(vec![0x12, 0x00]));
+    }
+
+    #[test]
+    fn programs_must_fit() {
+        assert_eq!(check_fits(&[0x00, 0xe0], 2), Ok(()));
+        assert_eq!(check_fits(&[], 2), Err(LoadError::Empty));
+        assert_eq!(check_fits(&[0; 3], 2), Err(LoadError::TooLarge { size: 3, room: 2 }));
+        assert_eq!(LoadError::TooLarge { size: 3, room: 2 }.to_string(), "the ROM is 3 bytes, but there's only room for 2");
+    }
+}
@@ -3,24 +3,87 @@ extern crate rand;
 
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
-use std::path::PathBuf;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::Texture;
+use sdl2::rect::Rect;
+use sdl2::mouse::MouseButton;
+use sdl2::audio::AudioSpecDesired;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use std::fs;
 use std::fs::File;
-use std::io::prelude::*;
-use std::collections::HashMap;
+use std::io::BufWriter;
 use std::time::Instant;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::env;
+use rand::{FromEntropy, Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 mod opcodes;
 use opcodes::*;
 mod hexfont;
 use hexfont::*;
+mod palette;
+use palette::*;
+mod hotkeys;
+use hotkeys::*;
+mod persistence;
+use persistence::*;
+mod quirks;
+use quirks::*;
+mod image;
+use image::Image;
+mod filters;
+use filters::Filter;
+mod keymap;
+use keymap::{host_key_name, HeldKeys, KeyMap, KeySource};
+mod gamepad;
+use gamepad::{Gamepads, PadMap};
+mod keypad;
+use keypad::{Keypad, KEYPAD_SIZE};
+mod audio;
+use audio::Beeper;
+mod wav;
+use wav::AudioRecorder;
+mod gif;
+use gif::GifRecorder;
+mod pacing;
+use pacing::Pacing;
+mod textfont;
+mod osd;
+use osd::{draw_box, Anchor, Osd, Overlay};
+mod settings;
+use settings::{settings_path, Settings};
+mod savestate;
+mod menu;
+use menu::{Menu, MenuAction};
+mod browser;
+use browser::{Browser, BrowserAction};
+mod watch;
+use watch::{FileWatcher, WatchMode};
+mod cli;
+use cli::{Command, Options, USAGE};
+mod commands;
+mod disasm;
+mod asm;
+mod sha1;
+use sha1::sha1_hex;
+mod romdb;
+use romdb::RomInfo;
+mod help;
+use help::help_lines;
+mod loader;
+use loader::{check_fits, is_stdin, read_program, LoadError};
+mod memory;
+use memory::{MemoryLayout, FONT_SIZE, VIP_DISPLAY, VIP_DISPLAY_SIZE, VIP_STACK};
+mod fault;
+use fault::Fault;
 
 struct ComputerState {
-    // 4K main memory
-    memory: [u8; 4096],
+    // Main memory, 4K unless the layout says otherwise
+    memory: Vec<u8>,
+    // Where the program and font go, and how much memory there is
+    layout: MemoryLayout,
     // General purpose registers V0..VE + special, VF
     registers: [u8; 16],
     // Index register
@@ -33,45 +96,153 @@ struct ComputerState {
     delay_timer: u8,
     // Sound timer
     sound_timer: u8,
-    // Stack
+    // Value last written to the sound timer, until the host picks it up
+    sound_timer_write: Option<u8>,
+    // Stack, as deep as the layout allows
     stack: Vec<u16>,
+    // Set when the program crashes the machine, which then stops
+    fault: Option<Fault>,
     // Keyboard state
     keys: [bool; 16],
     // Blocked on keypress?
     waiting_for_keypress: bool,
     // The key that was pressed during the last block will be stored in this register
-    waiting_for_keypress_target: usize
+    waiting_for_keypress_target: usize,
+    // Key pressed while blocked, which will unblock when it is released
+    waiting_for_key_release: Option<u8>,
+    // Blocked until the next frame?
+    waiting_for_vblank: bool,
+    // Interpreter-specific behaviours
+    quirks: Quirks,
+    // For CXNN
+    rng: StdRng,
+    // What the program is, if it's one we know
+    rom: Option<&'static RomInfo>,
+    // How many bytes the font takes up, to clear when another replaces it
+    font_size: usize
 }
 
 impl ComputerState {
     pub fn new() -> ComputerState {
+        ComputerState::with_layout(MemoryLayout::default())
+    }
+
+    pub fn with_layout(layout: MemoryLayout) -> ComputerState {
         let mut c =
         ComputerState {
-            memory: [0u8; 4096],
+            memory: vec![0u8; layout.size],
             registers: [0u8; 16],
             index: 0,
-            program_counter: 0x200, // the start of program memory
+            program_counter: layout.load_address, // the start of program memory
             gfx: [0u8; (64 * 32)],
             delay_timer: 0,
             sound_timer: 0,
+            sound_timer_write: None,
             stack: Vec::<u16>::with_capacity(16),
+            fault: None,
             keys: [false; 16],
             waiting_for_keypress: false,
-            waiting_for_keypress_target: 0
+            waiting_for_keypress_target: 0,
+            waiting_for_key_release: None,
+            waiting_for_vblank: false,
+            quirks: Quirks::default(),
+            rng: StdRng::from_entropy(),
+            rom: None,
+            font_size: FONT_SIZE,
+            layout
         };
 
         // load font into lomem
         let font = get_hex_font();
-        for offset in 0..font.len() {
-            c.memory[offset] = font[offset];
-        }
+        let font_address = c.layout.font_address as usize;
+        c.memory[font_address..][..font.len()].copy_from_slice(&font);
 
         c
     }
 
+    // Puts a font in place of the usual one, which font_for has made
+    // sure fits
+    pub fn load_font(&mut self, font: &Font) {
+        let bytes = font.bytes();
+        let font_address = self.layout.font_address as usize;
+        // a smaller font mustn't leave the big digits of the last one behind
+        for byte in self.memory[font_address..][..self.font_size].iter_mut() {
+            *byte = 0;
+        }
+        self.memory[font_address..][..bytes.len()].copy_from_slice(&bytes);
+        self.font_size = bytes.len();
+    }
+
+    // Addresses past the end of memory wrap around to the start, as
+    // there may be less of it than I can reach
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize % self.memory.len()]
+    }
+
+    fn poke(&mut self, address: u16, value: u8) {
+        let address = address as usize % self.memory.len();
+        self.memory[address] = value;
+        if self.layout.vip_areas {
+            self.vip_area_written(address);
+        }
+    }
+
+    // The display and stack are kept as they were, and mirrored into
+    // memory on the VIP, so a program writing there changes them too
+    fn vip_area_written(&mut self, address: usize) {
+        let display = VIP_DISPLAY as usize;
+        if (display..display + VIP_DISPLAY_SIZE).contains(&address) {
+            let first = (address - display) * 8;
+            for bit in 0..8 {
+                self.gfx[first + bit] = (self.memory[address] >> (7 - bit)) & 1;
+            }
+        }
+        let stack = VIP_STACK as usize;
+        if (stack..ComputerState::vip_stack_slot(self.stack.len())).contains(&address) {
+            let depth = (address - stack) / 2;
+            let slot = ComputerState::vip_stack_slot(depth);
+            self.stack[depth] = (self.memory[slot] as u16) << 8 | self.memory[slot + 1] as u16;
+        }
+    }
+
+    // Entries go up from 0xEA0, 2 bytes each, high byte first
+    fn vip_stack_slot(depth: usize) -> usize {
+        VIP_STACK as usize + depth * 2
+    }
+
+    fn set_pixel(&mut self, target: usize, pixel: u8) {
+        self.gfx[target] = pixel;
+        if self.layout.vip_areas {
+            let address = VIP_DISPLAY as usize + target / 8;
+            let bit = 0x80 >> (target % 8);
+            if pixel != 0 {
+                self.memory[address] |= bit;
+            } else {
+                self.memory[address] &= !bit;
+            }
+        }
+    }
+
+    fn push_stack(&mut self, address: u16) {
+        if self.layout.vip_areas {
+            // with_stack_depth keeps this clear of the display
+            let slot = ComputerState::vip_stack_slot(self.stack.len());
+            self.memory[slot] = (address >> 8) as u8;
+            self.memory[slot + 1] = address as u8;
+        }
+        self.stack.push(address);
+    }
+
+    // Leaves the program counter on the instruction that faulted, to
+    // see in the debugger, and says where that is
+    fn stop_at_fault(&mut self) -> u16 {
+        self.program_counter = self.program_counter.wrapping_sub(2);
+        self.program_counter
+    }
+
     fn skip_next_instruction(&mut self) {
         // advance the instruction pointer
-        self.program_counter += 2; // 2 bytes (16 bit instructions)
+        self.program_counter = self.program_counter.wrapping_add(2); // 2 bytes (16 bit instructions)
     }
 
     fn write_pixel_row(&mut self, x: u8, y: u8, row: u8) -> bool {
@@ -95,7 +266,7 @@ impl ComputerState {
                 a_pixel_became_zero = true;
             }
 
-            self.gfx[target] = pixel;
+            self.set_pixel(target, pixel);
 
             offset >>= 1;
         }
@@ -104,46 +275,48 @@ impl ComputerState {
         a_pixel_became_zero
     }
 
-    pub fn load_program(&mut self, path: &str) {
-        let relative_path = PathBuf::from(path);
-        let mut absolute_path = std::env::current_dir().unwrap();
-        absolute_path.push(relative_path);
-        let target = absolute_path.as_path();
-
-        if !target.exists() {
-            // Not found...
-            let target_path = target.to_str().unwrap(); // FIXME: might cause a crash if it's a really mangled string. need a safer unwrap.
-            panic!(
-                "Could not find the file '{}' to load as a CHIP-8 program.",
-                target_path
-            );
+    // Puts a program in memory at the load address, if it fits. ROMs are
+    // read with read_program, from a file or standard input for "-", and
+    // the bytes kept to reset without reading them again.
+    pub fn load_bytes(&mut self, program: &[u8]) -> Result<(), LoadError> {
+        check_fits(program, self.layout.program_room())?;
+        let load_address = self.layout.load_address as usize;
+        self.memory[load_address..][..program.len()].copy_from_slice(program);
+
+        // a program we know gets the quirks it was written for
+        self.rom = romdb::identify(program);
+        if let Some(rom) = self.rom {
+            self.quirks = rom.quirks.clone();
         }
+        Ok(())
+    }
 
-        // Load the program into RAM
-        let target_path = target.to_str().unwrap();
-        println!("Loading CHIP-8 program '{}'", target_path);
-
-        let mut file = File::open(target_path).unwrap();
-        let mut buf = Vec::<u8>::new();
-
-        match file.read_to_end(&mut buf) {
-            Ok(length) => {
-                println!("Loaded {} byte(s)", length);
-
-                if length > self.memory.len() - 0x200 {
-                    println!("Warning: too big for main memory. Will probably crash.");
-                }
+    // Makes CXNN give the same numbers on every run
+    pub fn seed(&mut self, seed: u64) {
+        let mut bytes = [0u8; 32];
+        bytes[..8].copy_from_slice(&seed.to_le_bytes());
+        self.rng = StdRng::from_seed(bytes);
+    }
 
-                // Start loading at 0x200
-                for (i, b) in buf.iter().enumerate() {
-                    self.memory[i + 0x200] = *b;
-                }
-            },
-            Err(e) => {
-                println!("Error loading file: {}", e);
-                std::process::exit(1);
-            }
+    // Back to the start of a program that's changed, leaving the display,
+    // registers, timers and quirks as they were
+    pub fn restart(&mut self, program: &[u8]) -> Result<(), LoadError> {
+        check_fits(program, self.layout.program_room())?;
+        let load_address = self.layout.load_address as usize;
+        let room = self.layout.program_room();
+        for byte in self.memory[load_address..][..room].iter_mut() {
+            *byte = 0;
         }
+        let quirks = self.quirks.clone();
+        self.load_bytes(program)?;
+        self.quirks = quirks;
+        self.program_counter = self.layout.load_address;
+        self.stack.clear();
+        self.fault = None;
+        self.waiting_for_keypress = false;
+        self.waiting_for_key_release = None;
+        self.waiting_for_vblank = false;
+        Ok(())
     }
 
     pub fn decode(&self, instruction: u16) -> Chip8Opcode {
@@ -312,6 +485,9 @@ impl ComputerState {
             else if bottom_byte == 0x29 {
                 return Chip8Opcode::UseSprite(register);
             }
+            else if bottom_byte == 0x30 {
+                return Chip8Opcode::UseBigSprite(register);
+            }
             else if bottom_byte == 0x33 {
                 return Chip8Opcode::ReadRegisterAsBCD(register);
             }
@@ -335,7 +511,7 @@ impl ComputerState {
             Chip8Opcode::DisplayClear => {
                 for i in 0..self.gfx.len() {
                     // FIXME: is a more succinct way to do this?
-                    self.gfx[i] = 0;
+                    self.set_pixel(i, 0);
                 }
             },
             Chip8Opcode::ReturnFromSubroutine => {
@@ -344,7 +520,8 @@ impl ComputerState {
                         self.program_counter = return_to;
                     },
                     None => {
-                        panic!("pc={} stack underflow", self.program_counter);
+                        let address = self.stop_at_fault();
+                        self.fault = Some(Fault::StackUnderflow { address });
                     }
                 }
             },
@@ -352,8 +529,15 @@ impl ComputerState {
                 self.program_counter = address;
             },
             Chip8Opcode::CallSub(sub_address) => {
-                self.stack.push(self.program_counter); // should be 'after' the CALL since we +2 before going in here
-                self.program_counter = sub_address;
+                let depth = self.stack.len();
+                if self.layout.stack_depth.has_room(depth) {
+                    let return_to = self.program_counter; // should be 'after' the CALL since we +2 before going in here
+                    self.push_stack(return_to);
+                    self.program_counter = sub_address;
+                } else {
+                    let address = self.stop_at_fault();
+                    self.fault = Some(Fault::StackOverflow { address, depth });
+                }
             },
             Chip8Opcode::SkipNextIfEqual(r1, value) => {
                 let v1 = self.get_register(r1);
@@ -479,7 +663,8 @@ impl ComputerState {
                 self.program_counter = offset + base;
             },
             Chip8Opcode::Random(target_register, value) => {
-                self.set_register(target_register, rand::random::<u8>() & value);
+                let roll = self.rng.gen::<u8>();
+                self.set_register(target_register, roll & value);
             },
             Chip8Opcode::Draw(x_reg, y_reg, height) => {
                 // sprites are 8 pixels wide
@@ -492,13 +677,17 @@ impl ComputerState {
                 let vy = self.get_register(y_reg);
 
                 for row in 0..height {
-                    let source = self.memory[(self.index + row as u16) as usize];
+                    let source = self.peek(self.index.wrapping_add(row as u16));
                     let y = (vy + row) % 32; // FIXME: constant for this
 
                     if self.write_pixel_row(vx, y, source) {
                         self.set_register(0xf, 1);
                     }
                 }
+
+                if self.quirks.display_wait {
+                    self.waiting_for_vblank = true;
+                }
             },
             Chip8Opcode::SkipNextIfKeyDown(register) => {
                 let key = self.get_register(register) as usize;
@@ -519,6 +708,7 @@ impl ComputerState {
             Chip8Opcode::BlockOnKeyPress(target_register) => {
                 self.waiting_for_keypress = true;
                 self.waiting_for_keypress_target = target_register as usize;
+                self.waiting_for_key_release = None;
             },
             Chip8Opcode::SetDelayTimer(target_register) => {
                 let value = self.get_register(target_register);
@@ -527,17 +717,26 @@ impl ComputerState {
             Chip8Opcode::SetSoundTimer(target_register) => {
                 let value = self.get_register(target_register);
                 self.sound_timer = value;
+                self.sound_timer_write = Some(value);
             },
             Chip8Opcode::AddToIndexRegister(target_register) => {
                 let value = self.get_register(target_register);
-                self.index += value as u16; // any special overflow conditions?
+                // with 64K of memory, I can be pushed past the top
+                self.index = self.index.wrapping_add(value as u16);
             },
             Chip8Opcode::UseSprite(register) => {
                 let character = self.get_register(register) as u16;
 
                 // each letter is 5 bytes long,
-                // and stored in ROM starting at 0x0
-                self.index = 0x0 + (character * 5);
+                // and stored where the layout puts the font
+                self.index = self.layout.font_address + (character * 5);
+            },
+            Chip8Opcode::UseBigSprite(register) => {
+                let character = self.get_register(register) as u16;
+
+                // 10 bytes each, straight after the small ones, if
+                // the font has any
+                self.index = self.layout.font_address + FONT_SIZE as u16 + (character * 10);
             },
             Chip8Opcode::ReadRegisterAsBCD(register) => {
                 // Store BCD rep of Vx in I, I+1 and I+2
@@ -545,20 +744,22 @@ impl ComputerState {
                 let hundreds = value / 100;
                 let tens = (value - hundreds * 100) / 10;
                 let ones = value - (hundreds * 100 + tens * 10);
-                self.memory[self.index as usize] = hundreds;
-                self.memory[(self.index + 1) as usize] = tens;
-                self.memory[(self.index + 2) as usize] = ones;
+                let index = self.index;
+                self.poke(index, hundreds);
+                self.poke(index.wrapping_add(1), tens);
+                self.poke(index.wrapping_add(2), ones);
             },
             Chip8Opcode::DumpRegisters(stop_register) => {
                 // Store v0 through vX in memory starting at I
                 for register in 0..=stop_register {
                     let value = self.get_register(register);
-                    self.memory[(self.index + register as u16) as usize] = value;
+                    let address = self.index.wrapping_add(register as u16);
+                    self.poke(address, value);
                 }
             },
             Chip8Opcode::FillRegisters(stop_register) => {
                 for register in 0..=stop_register {
-                    let value = self.memory[(self.index + register as u16) as usize];
+                    let value = self.peek(self.index.wrapping_add(register as u16));
                     self.set_register(register, value);
                 }
             },
@@ -570,12 +771,16 @@ impl ComputerState {
     }
 
     pub fn step(&mut self) {
+        if self.is_blocked() {
+            return;
+        }
+
         // fetch
-        let pc: usize = self.program_counter as usize;
-        let instruction = (self.memory[pc] as u16) << 8 | (self.memory[pc + 1] as u16);
+        let pc = self.program_counter;
+        let instruction = (self.peek(pc) as u16) << 8 | (self.peek(pc.wrapping_add(1)) as u16);
 
         // advance pointer to next instruction (execute may change address)
-        self.program_counter += 2;
+        self.program_counter = pc.wrapping_add(2);
 
         // decode
         let decoded = self.decode(instruction);
@@ -586,6 +791,80 @@ impl ComputerState {
         self.execute(decoded);
     }
 
+    pub fn key_down(&mut self, key: u8) {
+        self.keys[key as usize] = true;
+
+        if self.waiting_for_keypress && self.waiting_for_key_release.is_none() {
+            if self.quirks.key_wait_on_press {
+                self.finish_key_wait(key);
+            } else {
+                // remember it, and finish once it comes back up
+                self.waiting_for_key_release = Some(key);
+            }
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.keys[key as usize] = false;
+
+        if self.waiting_for_keypress && self.waiting_for_key_release == Some(key) {
+            self.finish_key_wait(key);
+        }
+    }
+
+    fn finish_key_wait(&mut self, key: u8) {
+        // Disable the block
+        self.waiting_for_keypress = false;
+        self.waiting_for_key_release = None;
+        // Store the key that was pressed in the register
+        // they told us to store it in
+        self.registers[self.waiting_for_keypress_target] = key;
+    }
+
+    // For the register overlay
+    pub fn register_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("PC {:03X}  I {:03X}", self.program_counter, self.index)];
+        for (row, values) in self.registers.chunks(4).enumerate() {
+            let cells: Vec<String> = values.iter().enumerate()
+                .map(|(column, value)| format!("V{:X} {:02X}", row * 4 + column, value))
+                .collect();
+            lines.push(cells.join("  "));
+        }
+        lines.push(format!("DT {:02X}  ST {:02X}  SP {}", self.delay_timer, self.sound_timer, self.stack.len()));
+
+        // the innermost calls, deepest last
+        let first = self.stack.len().saturating_sub(STACK_LINES * 4);
+        for (row, addresses) in self.stack[first..].chunks(4).enumerate() {
+            let cells: Vec<String> = addresses.iter().enumerate()
+                .map(|(column, address)| format!("S{} {:03X}", first + row * 4 + column, address))
+                .collect();
+            lines.push(cells.join("  "));
+        }
+        lines
+    }
+
+    // The sound timer counts down on its own, so the host only needs to know
+    // when the program sets it to start (or cut short) a beep
+    pub fn take_sound_timer_write(&mut self) -> Option<u8> {
+        self.sound_timer_write.take()
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.waiting_for_keypress || self.waiting_for_vblank || self.fault.is_some()
+    }
+
+    // Called at the end of every 60hz frame
+    pub fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
     fn get_register(&self, register_index: Chip8Register) -> Chip8Value {
         self.registers[register_index as usize]
     }
@@ -595,63 +874,343 @@ impl ComputerState {
     }
 }
 
-fn draw_screen<T : sdl2::render::RenderTarget>(chip8: &ComputerState, canvas: &mut sdl2::render::Canvas<T>) {
-    // FIXME: fix this so that 800x600 is no longer hardcoded
-    const K_WIDTH : u32 = 800;
-    const K_HEIGHT : u32 = 600;
+// Stack entries in the register overlay, four to a line
+const STACK_LINES: usize = 2;
 
-    let pixel_size = std::cmp::min(K_WIDTH / 64, K_HEIGHT / 32);
+// The size of the window when no scale is given
+const DEFAULT_WIDTH : u32 = 800;
+const DEFAULT_HEIGHT : u32 = 600;
 
-    // Centre the screen
-    let start_x = (K_WIDTH - (64 * pixel_size)) / 2;
-    let start_y = (K_HEIGHT - (32 * pixel_size)) / 2;
+// Where things go in the window
+struct Layout {
+    window: Rect,
+    // Always a whole multiple of 64x32
+    display: Rect,
+    keypad: Option<Rect>
+}
 
-    // FIXME: There is probably a faster way to do this. FillRects?
-    for y in 0..32 {
-        for x in 0..64 {
-            let val = chip8.gfx[y * 64 + x];
+impl Layout {
+    // With a scale the window fits around the display, otherwise the
+    // display is as big as fits in the default window
+    fn new(scale: Option<u32>, show_keypad: bool) -> Layout {
+        // the keypad takes a strip down the right hand side
+        let keypad_width = if show_keypad { KEYPAD_SIZE } else { 0 };
+        let (window_width, window_height) = match scale {
+            Some(scale) => (64 * scale + keypad_width, std::cmp::max(32 * scale, keypad_width)),
+            None => (DEFAULT_WIDTH, DEFAULT_HEIGHT)
+        };
+        let available_width = window_width - keypad_width;
 
-            if val > 0 {
-                let rect = sdl2::rect::Rect::new(
-                    (start_x + (x as u32 * pixel_size)) as i32,
-                    (start_y + (y as u32 * pixel_size)) as i32,
-                    pixel_size, pixel_size);
+        let pixel_size = std::cmp::min(available_width / 64, window_height / 32);
+        let (width, height) = (64 * pixel_size, 32 * pixel_size);
 
-                canvas.set_draw_color(Color::RGB(255, 140, 0)); // dark orange
+        // Centre the screen
+        let display = Rect::new(
+            ((available_width - width) / 2) as i32,
+            ((window_height - height) / 2) as i32,
+            width, height);
 
-                canvas.fill_rect(rect).unwrap();
-            }
-        }
+        let keypad = if show_keypad {
+            Some(Rect::new(available_width as i32, ((window_height - KEYPAD_SIZE) / 2) as i32, KEYPAD_SIZE, KEYPAD_SIZE))
+        } else {
+            None
+        };
+
+        Layout { window: Rect::new(0, 0, window_width, window_height), display, keypad }
+    }
+
+    // Size of one CHIP-8 pixel in the window
+    fn pixel_size(&self) -> u32 {
+        self.display.width() / 64
     }
 }
 
-struct BeeperTone {
-    volume: f32,
-    phase_inc : f32,
-    phase: f32
+// Colour and filter the display into a picture the size it will appear in the window
+fn render_screen(intensity: &[f32], palette: &Palette, filter: Filter, pixel_size: u32) -> Image {
+    let mut source = Image::new(64, 32, palette.background);
+    for (pixel, val) in source.pixels.iter_mut().zip(intensity) {
+        *pixel = palette.shade(*val);
+    }
+
+    filters::apply(filter, &source, pixel_size)
 }
 
-impl AudioCallback for BeeperTone {
-    type Channel = f32;
+fn draw_screen<T : sdl2::render::RenderTarget>(screen: &Image, area: Rect, texture: &mut Texture, canvas: &mut sdl2::render::Canvas<T>) {
+    texture.update(None, &screen.to_rgb24(), (screen.width * 3) as usize).unwrap();
+    canvas.copy(texture, None, area).unwrap();
+}
+
+// The game, if it's one we know, and the emulator's status, if it isn't
+// just running, go in the title bar
+fn window_title(rom: Option<&RomInfo>, status: Option<String>) -> String {
+    let name = match rom {
+        Some(rom) => format!("{} - chip8.rs", rom.title),
+        None => "chip8.rs".to_string()
+    };
+    match status {
+        Some(status) => format!("{} ({})", name, status),
+        None => name
+    }
+}
 
-    fn callback(&mut self, out: &mut [f32]) {
-        // 440 / frequency
+// Named after the time so that screenshots and recordings don't overwrite each other
+fn capture_path(extension: &str) -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("chip8-{}.{}", seconds, extension)
+}
 
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 { self.volume } else { -self.volume };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-            // stolen from the SDL2 rust square wave example: https://github.com/Rust-SDL2/rust-sdl2/blob/master/examples/audio-squarewave.rs
+// Each CHIP-8 pixel becomes a square this big in GIF recordings
+const GIF_SCALE: usize = 4;
+
+fn start_recording(path: String, palette: &Palette, osd: &mut Osd) -> Option<(String, GifRecorder<BufWriter<File>>)> {
+    match GifRecorder::create(&path, palette, GIF_SCALE) {
+        Ok(recorder) => {
+            osd.message(&format!("Recording to {}", path));
+            Some((path, recorder))
+        },
+        Err(e) => {
+            osd.message(&format!("Could not record to '{}': {}", path, e));
+            None
         }
     }
 }
 
+// Says how it went
+fn stop_recording(path: &str, recorder: GifRecorder<BufWriter<File>>) -> String {
+    match recorder.finish() {
+        Ok(_) => format!("Saved recording to {}", path),
+        Err(e) => format!("Could not save recording: {}", e)
+    }
+}
+
+// A fresh machine with the program loaded, ready to run. The quirks
+// given win over those the ROM database has for the program, as they
+// start from them anyway.
+fn start_program(program: &[u8], layout: &MemoryLayout, font: &Font, quirks: &Quirks,
+                 seed: Option<u64>, start: Option<u16>) -> Result<ComputerState, LoadError> {
+    let mut chip8 = ComputerState::with_layout(layout.clone());
+    chip8.load_font(font);
+    if let Some(seed) = seed {
+        chip8.seed(seed);
+    }
+    chip8.load_bytes(program)?;
+    chip8.quirks = quirks.clone();
+    if let Some(start) = start {
+        chip8.program_counter = start;
+    }
+    Ok(chip8)
+}
+
+// What to call a game in the settings file
+fn game_name(program_path: &str) -> String {
+    Path::new(program_path).file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+// Save states are kept next to the ROM, like controller mappings
+fn state_path(program_path: &str) -> Result<String, String> {
+    if program_path.is_empty() {
+        return Err("no game loaded".to_string());
+    }
+    if is_stdin(program_path) {
+        return Err("the game came from standard input, with nowhere to keep states".to_string());
+    }
+    Ok(format!("{}.state", program_path))
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("usage: chip8 [COMMAND] [OPTIONS] [FILE], or 'chip8 help' for more");
+    std::process::exit(1);
+}
+
+// What per-game settings go by, None until there's a program
+fn rom_hash(program: &[u8]) -> Option<String> {
+    if program.is_empty() { None } else { Some(sha1_hex(program)) }
+}
+
+// The settings to run a ROM with: the saved defaults, then the game's
+// own section, then the command line
+fn settings_for(settings: &Settings, options: &Options, hash: Option<&str>) -> Settings {
+    let mut current = match hash {
+        Some(hash) => settings.for_game(hash),
+        None => settings.clone()
+    };
+    apply_options(&mut current, options);
+    current
+}
+
+// Options on the command line win over saved settings. palettes_for
+// checks the theme is one it knows.
+fn apply_options(settings: &mut Settings, options: &Options) {
+    if let Some(count) = options.instructions_per_frame {
+        settings.instructions_per_frame = count;
+    }
+    if let Some(ref quirks) = options.quirks {
+        settings.quirks = quirks.clone();
+    }
+    if options.display_wait {
+        settings.quirks.display_wait = true;
+    }
+    if options.key_wait_on_press {
+        settings.quirks.key_wait_on_press = true;
+    }
+    if let Some(ref keys) = options.keys {
+        settings.keys = keys.clone();
+    }
+    if let Some(ref font) = options.font {
+        settings.font = font.clone();
+    }
+    if let Some(ref theme) = options.theme {
+        settings.palette = theme.clone();
+    }
+    if options.foreground.is_some() || options.background.is_some() {
+        settings.palette = "custom".to_string();
+    }
+    if let Some(volume) = options.volume {
+        settings.volume = volume;
+    }
+    if let Some(frequency) = options.frequency {
+        settings.tone = frequency;
+    }
+    if let Some(waveform) = options.waveform {
+        settings.waveform = waveform;
+    }
+    if let Some(ref roms) = options.roms {
+        settings.roms = roms.clone();
+    }
+}
+
+// The machine the command line asks for, which is the usual 4K one
+// unless it says otherwise
+fn layout_for(options: &Options) -> Result<MemoryLayout, String> {
+    let default = MemoryLayout::default();
+    let mut layout = MemoryLayout::new(
+        options.load_address.unwrap_or(default.load_address),
+        options.font_address.unwrap_or(default.font_address),
+        options.memory_size.unwrap_or(default.size)
+    )?;
+    if options.vip_memory {
+        layout = layout.with_vip_areas()?;
+    }
+    if let Some(depth) = options.stack_depth {
+        layout = layout.with_stack_depth(depth)?;
+    }
+    match options.start {
+        Some(start) if start as usize >= layout.size =>
+            Err(format!("--start 0x{:03X} is past the end of {} bytes of memory", start, layout.size)),
+        _ => Ok(layout)
+    }
+}
+
+// The font the settings ask for, if it fits where the layout puts it
+fn font_for(settings: &Settings, layout: &MemoryLayout) -> Result<Font, String> {
+    let font = Font::load(&settings.font)?;
+    layout.check_font(font.bytes().len())?;
+    Ok(font)
+}
+
+// The themes to cycle through, and which one to start with
+fn palettes_for(options: &Options, settings: &Settings) -> Result<(Vec<Palette>, usize), String> {
+    let mut palettes = builtin_themes();
+    let mut palette_index = match options.theme {
+        Some(ref name) => find_theme(&palettes, name).ok_or_else(|| {
+            let names: Vec<_> = palettes.iter().map(|theme| theme.name.clone()).collect();
+            format!("Unknown theme '{}', try one of: {}", name, names.join(", "))
+        })?,
+        None => find_theme(&palettes, &settings.palette).unwrap_or(0)
+    };
+
+    if options.foreground.is_some() || options.background.is_some() {
+        // custom colours start from the chosen theme, and join the cycle after the built-ins
+        let base = palettes[palette_index].clone();
+        palettes.push(Palette::new(
+            "custom",
+            options.foreground.unwrap_or(base.foreground),
+            options.background.unwrap_or(base.background)
+        ));
+        palette_index = palettes.len() - 1;
+    }
+    Ok((palettes, palette_index))
+}
+
 pub fn main() {
+    let options = cli::parse(env::args().skip(1)).unwrap_or_else(|e| usage_error(&e));
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let settings_file = settings_path();
+    let settings = Settings::load(&settings_file).unwrap_or_else(|e| {
+        println!("Ignoring saved settings: {}", e);
+        Settings::default()
+    });
+
+    if options.command == Command::Run && !options.headless {
+        run_window(options, settings, settings_file);
+    } else if let Err(e) = commands::run(&options, &settings) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// `settings` are as saved, to go back to after a game with its own
+fn run_window(options: Options, mut settings: Settings, settings_file: PathBuf) {
+    // Empty until one is picked in the ROM browser
+    let mut program_path = options.file.clone().unwrap_or_default();
+    // Only for the ROM given on the command line
+    let mut start_address = options.start;
+    // Every ROM gets the same machine, browsed or not
+    let memory_layout = layout_for(&options).unwrap_or_else(|e| usage_error(&e));
+    // Kept, rather than read again on reset, as it may have come from standard input
+    let mut program = if program_path.is_empty() {
+        Vec::new()
+    } else {
+        read_program(&program_path).unwrap_or_else(|e| usage_error(&format!("Could not load {}: {}", program_path, e)))
+    };
+    let mut game = rom_hash(&program);
+    // The settings in use, which the menu changes
+    let mut current = settings_for(&settings, &options, game.as_deref());
+
+    let (palettes, mut palette_index) = palettes_for(&options, &current).unwrap_or_else(|e| usage_error(&e));
+    let persistence_mode = options.persistence.unwrap_or(PersistenceMode::Off);
+    let mut filter = options.filter.unwrap_or(Filter::None);
+    let mut keymap = match options.keys {
+        Some(ref keys) => KeyMap::load(keys).unwrap_or_else(|e| usage_error(&e)),
+        None => KeyMap::load(&current.keys).unwrap_or_else(|e| {
+            println!("Ignoring saved key mapping: {}", e);
+            KeyMap::preset("qwerty").unwrap()
+        })
+    };
+    let mut font = match options.font {
+        Some(_) => font_for(&current, &memory_layout).unwrap_or_else(|e| usage_error(&e)),
+        None => font_for(&current, &memory_layout).unwrap_or_else(|e| {
+            println!("Ignoring saved font: {}", e);
+            Font::default()
+        })
+    };
+    let padmap_path = options.pad.clone();
+    let tone = current.tone();
+    let watch_mode = options.watch;
+
+    // the menu shows, and saves, what's actually in use
+    current.palette = palettes[palette_index].name.clone();
+
+    let mut padmap = match padmap_path {
+        Some(ref path) => PadMap::load(path),
+        None if program_path.is_empty() || is_stdin(&program_path) => Ok(PadMap::default()),
+        None => PadMap::for_program(&program_path)
+    }.unwrap_or_else(|e| usage_error(&e));
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let audio_subsystem = sdl_context.audio().unwrap();
+    let mut gamepads = Gamepads::new(sdl_context.game_controller().unwrap());
 
+    let layout = Layout::new(options.scale, options.keypad);
     let window = video_subsystem
-        .window("chip8.rs", 800, 600)
+        .window(&window_title(None, None), layout.window.width(), layout.window.height())
         .position_centered()
         .build()
         .unwrap();
@@ -662,114 +1221,485 @@ pub fn main() {
         samples: None
     };
 
-    let sound = audio_subsystem.open_playback(None, &desired_audio_spec, |spec| {
-        BeeperTone {
-            // 220hz is a pleasant atari-esque beep
-            phase_inc: 220.0 / spec.freq as f32,
-            phase: 0.0,
-            volume: 0.25
+    // Carry on without sound rather than refusing to run, e.g. when recording on a machine with no sound card
+    let sound = sdl_context.audio().and_then(|audio_subsystem| {
+        audio_subsystem.open_playback(None, &desired_audio_spec, |spec| {
+            Beeper::new(tone.clone(), spec.freq)
+        })
+    });
+    let mut sound = match sound {
+        Ok(sound) => {
+            // the beeper is silent until the sound timer is set, so it can run all the time
+            sound.resume();
+            Some(sound)
+        },
+        Err(e) => {
+            println!("No sound: {}", e);
+            None
         }
-    }).unwrap();
+    };
+
+    let mut recorder = options.wav.clone().map(|path| {
+        AudioRecorder::create(&path, 44_100)
+            .unwrap_or_else(|e| usage_error(&format!("Could not create '{}': {}", path, e)))
+    });
 
     let mut canvas = window.into_canvas().build().unwrap();
 
-    canvas.set_draw_color(Color::RGB(0, 255, 255));
+    canvas.set_draw_color(palettes[palette_index].background);
     canvas.clear();
     canvas.present();
 
-    // QWERTY keybindings, left handed
-    let keybindings: HashMap<Keycode, usize> = [
-    (Keycode::Num1, 1),
-    (Keycode::Num2, 2),
-    (Keycode::Num3, 3),
-    (Keycode::Num4, 0xc),
-    (Keycode::Q, 4),
-    (Keycode::W, 5),
-    (Keycode::E, 6),
-    (Keycode::R, 0xd),
-    (Keycode::A, 7),
-    (Keycode::S, 8),
-    (Keycode::D, 9),
-    (Keycode::F, 0xe),
-    (Keycode::Z, 0xa),
-    (Keycode::X, 0),
-    (Keycode::C, 0xb),
-    (Keycode::V, 0xf)
-    ].iter().cloned().collect();
+    let mut keypad = layout.keypad.map(Keypad::new);
+    // Keyboard, controllers and keypad can all hold the same CHIP-8 key
+    let mut held_keys = HeldKeys::default();
+
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(
+        PixelFormatEnum::RGB24, layout.display.width(), layout.display.height()).unwrap();
+
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    let mut chip8 = ComputerState::new();
+    let seed = options.seed;
+    let mut chip8 = if program_path.is_empty() {
+        ComputerState::with_layout(memory_layout.clone())
+    } else {
+        start_program(&program, &memory_layout, &font, &current.quirks, seed, start_address)
+            .unwrap_or_else(|e| usage_error(&format!("Could not load {}: {}", program_path, e)))
+    };
+
+    canvas.window_mut().set_title(&window_title(chip8.rom, None)).unwrap();
 
-    let args: Vec<_> = env::args().collect();
-    if args.len() > 1 {
-        let user_program_path = &args[1];
-        chip8.load_program(user_program_path);
-    }
-    else {
-        // default args package - TODO: make an actual config object if there are a lot of args added
-        chip8.load_program("roms/c8games/PONG");
-    }
+    let mut persistence = Persistence::new(persistence_mode);
+
+    let mut osd = Osd::new(Instant::now());
+
+    let mut gif = options.gif.clone().and_then(|path| start_recording(path, &palettes[palette_index], &mut osd));
+
+    let mut pacing = Pacing::default();
+
+    let mut menu: Option<Menu> = None;
+    // Whether F1 has the game's help showing
+    let mut showing_help = false;
+    // Whether closing the menu, or the help, should unpause
+    let mut paused_by_menu = false;
+    let mut paused_by_help = false;
+
+    // With no ROM to run, start by picking one
+    let mut browser = if program_path.is_empty() { Some(Browser::new(Path::new(&current.roms), &memory_layout)) } else { None };
+
+    // Follows whichever ROM is loaded
+    let mut watcher = match watch_mode {
+        Some(_) if !program_path.is_empty() => Some(FileWatcher::new(Path::new(&program_path))),
+        _ => None
+    };
 
     'running: loop {
         let start = Instant::now();
 
-        canvas.set_draw_color(Color::RGB(0, 0, 128));
+        let palette = &palettes[palette_index];
+        canvas.set_draw_color(palette.background);
         canvas.clear();
 
-        if !chip8.waiting_for_keypress {
-            chip8.step();
+        // Several emulated frames when fast forwarding, none when paused
+        let frames = if browser.is_some() { 0 } else { pacing.frames_to_run() };
+        let mut instructions_run = 0;
+        let faulted = chip8.fault.is_some();
+        for _ in 0..frames {
+            for _ in 0..current.instructions_per_frame {
+                if chip8.is_blocked() {
+                    break;
+                }
+                chip8.step();
+                instructions_run += 1;
+            }
+
+            let sound_timer_write = chip8.take_sound_timer_write();
+            if let (Some(ticks), Some(sound)) = (sound_timer_write, sound.as_mut()) {
+                sound.lock().play_ticks(ticks, pacing.speed());
+            }
+            if let Some(ref mut wav) = recorder {
+                if let Err(e) = wav.frame(&current.tone(), sound_timer_write) {
+                    osd.message(&format!("Stopped recording sound: {}", e));
+                    recorder = None;
+                }
+            }
+
+            if let Some((path, mut recorder)) = gif.take() {
+                match recorder.frame(&chip8.gfx, palette) {
+                    Ok(()) => gif = Some((path, recorder)),
+                    Err(e) => osd.message(&format!("Stopped recording: {}", e))
+                }
+            }
+
+            persistence.update(&chip8.gfx);
+
+            // Since we try to sleep for 60hz, just assume we can
+            // update timers with impunity.
+            chip8.vblank();
+        }
+        // the machine stays stopped until it's reset, so say why once
+        if let (false, Some(fault)) = (faulted, chip8.fault.as_ref()) {
+            osd.message(&format!("Stopped: {}", fault));
         }
 
         // draw contents of screen memory
-        draw_screen(&chip8, &mut canvas);
+        let screen = render_screen(persistence.intensity(), palette, filter, layout.pixel_size());
+        draw_screen(&screen, layout.display, &mut texture, &mut canvas);
 
-        if chip8.sound_timer <= 0 {
-            sound.pause(); // stop playing sound
-        } else {
-            sound.resume(); // continue playing sound
+        if let Some(ref keypad) = keypad {
+            keypad.draw(&chip8.keys, palette, &mut canvas);
         }
 
+        // to tell when a hotkey pauses or changes the speed, or another game is loaded
+        let status = pacing.status();
+        let rom = chip8.rom;
+
+        // picked in the browser, or dropped on the window
+        let mut rom_to_load: Option<PathBuf> = None;
+        // loaded or rebuilt, so the settings for it need looking up
+        let mut game_changed = false;
+
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'running,
+                Event::Quit { .. } => break 'running,
+                // holding a hotkey or Escape down shouldn't keep toggling it
+                Event::KeyDown { keycode: Some(key), repeat: true, .. } if key == Keycode::Escape || hotkey_for(key).is_some() => {},
+                Event::KeyDown { keycode: Some(key), .. } if menu.is_some() => {
+                    let before = current.clone();
+                    let action = menu.as_mut().unwrap().key_down(key, &mut current, &palettes);
+                    let close = match action {
+                        MenuAction::Nothing => false,
+                        MenuAction::Close => true,
+                        MenuAction::SettingsChanged => {
+                            palette_index = find_theme(&palettes, &current.palette).unwrap_or(palette_index);
+                            chip8.quirks = current.quirks.clone();
+                            keymap = KeyMap::load(&current.keys).unwrap_or(keymap);
+                            if let Some(ref mut sound) = sound {
+                                sound.lock().set_tone(current.tone());
+                            }
+                            // a game with settings of its own keeps them to itself
+                            match game {
+                                Some(ref hash) if settings.has_game(hash) => settings.change_game(hash, &game_name(&program_path), &before, &current),
+                                _ => settings.change_defaults(&before, &current)
+                            }
+                            if let Err(e) = settings.save(&settings_file) {
+                                osd.message(&format!("Could not save settings: {}", e));
+                            }
+                            false
+                        },
+                        MenuAction::SaveForGame => {
+                            match game {
+                                Some(ref hash) => {
+                                    // what the game started out with, so the command line isn't saved along with the menu's changes
+                                    let launched = settings_for(&settings, &options, Some(hash));
+                                    settings.change_game(hash, &game_name(&program_path), &launched, &current);
+                                    match settings.save(&settings_file) {
+                                        Ok(()) => osd.message(&format!("Saved settings for {}", game_name(&program_path))),
+                                        Err(e) => osd.message(&format!("Could not save settings: {}", e))
+                                    }
+                                },
+                                None => osd.message("No game loaded")
+                            }
+                            false
+                        },
+                        MenuAction::SaveState => {
+                            let saved = state_path(&program_path)
+                                .and_then(|path| fs::write(&path, chip8.save_state()).map_err(|e| e.to_string()));
+                            match saved {
+                                Ok(()) => osd.message("State saved"),
+                                Err(e) => osd.message(&format!("Could not save state: {}", e))
+                            }
+                            true
+                        },
+                        MenuAction::LoadState => {
+                            let loaded = state_path(&program_path)
+                                .and_then(|path| fs::read(&path).map_err(|e| e.to_string()))
+                                .and_then(|bytes| chip8.load_state(&bytes));
+                            match loaded {
+                                Ok(()) => osd.message("State loaded"),
+                                Err(e) => osd.message(&format!("Could not load state: {}", e))
+                            }
+                            true
+                        },
+                        MenuAction::BrowseRoms => {
+                            // stays paused until the browser closes
+                            menu = None;
+                            browser = Some(Browser::new(Path::new(&current.roms), &memory_layout));
+                            false
+                        },
+                        MenuAction::Quit => break 'running
+                    };
+
+                    if close {
+                        menu = None;
+                        if paused_by_menu {
+                            pacing.toggle_pause();
+                            paused_by_menu = false;
+                        }
+                    }
+                },
+                Event::KeyDown { keycode: Some(key), .. } if browser.is_some() => {
+                    match browser.as_mut().unwrap().key_down(key) {
+                        BrowserAction::Nothing => {},
+                        BrowserAction::Close if program_path.is_empty() => break 'running,
+                        BrowserAction::Close => browser = None,
+                        BrowserAction::Launch(path) => rom_to_load = Some(path)
+                    }
+
+                    if browser.is_none() && paused_by_menu {
+                        pacing.toggle_pause();
+                        paused_by_menu = false;
+                    }
+                },
+                Event::DropFile { filename, .. } => rom_to_load = Some(PathBuf::from(filename)),
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    menu = Some(Menu::default());
+                    paused_by_menu = !pacing.is_paused();
+                    if paused_by_menu {
+                        pacing.toggle_pause();
+                    }
+                },
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keycode, scancode, ..
                 } => {
-                    if keybindings.contains_key(&key) {
-                        chip8.keys[keybindings[&key]] = true;
-
-                        if chip8.waiting_for_keypress {
-                            // Disable the block
-                            chip8.waiting_for_keypress = false;
-                            // Store the key that was pressed in the register
-                            // they told us to store it in
-                            chip8.registers[chip8.waiting_for_keypress_target] = keybindings[&key] as u8;
+                    if let Some(hotkey) = keycode.and_then(hotkey_for) {
+                        match hotkey {
+                            Hotkey::CyclePalette => {
+                                palette_index = (palette_index + 1) % palettes.len();
+                                osd.message(&format!("Palette: {}", palettes[palette_index].name));
+                            },
+                            Hotkey::CyclePersistence => {
+                                let mode = persistence.mode().next();
+                                persistence.set_mode(mode);
+                                osd.message(&format!("Persistence: {}", mode));
+                            },
+                            Hotkey::CycleFilter => {
+                                filter = filter.next();
+                                osd.message(&format!("Filter: {}", filter));
+                            },
+                            Hotkey::Mute => {
+                                if let Some(ref mut sound) = sound {
+                                    let mut beeper = sound.lock();
+                                    let muted = !beeper.is_muted();
+                                    beeper.set_muted(muted);
+                                    osd.message(if muted { "Sound muted" } else { "Sound on" });
+                                }
+                            },
+                            Hotkey::Screenshot => {
+                                let path = capture_path("bmp");
+                                match screen.save_bmp(&path) {
+                                    Ok(()) => osd.message(&format!("Saved screenshot to {}", path)),
+                                    Err(e) => osd.message(&format!("Could not save screenshot: {}", e))
+                                }
+                            },
+                            Hotkey::Record => {
+                                gif = match gif.take() {
+                                    Some((path, recorder)) => {
+                                        osd.message(&stop_recording(&path, recorder));
+                                        None
+                                    },
+                                    None => start_recording(capture_path("gif"), palette, &mut osd)
+                                };
+                            },
+                            Hotkey::Reset => {
+                                chip8 = start_program(&program, &memory_layout, &font, &current.quirks, seed, start_address).unwrap_or(chip8);
+                                if let Some(ref mut sound) = sound {
+                                    sound.lock().play_ticks(0, 1.0);
+                                }
+                                osd.message("Reset");
+                            },
+                            Hotkey::Pause => pacing.toggle_pause(),
+                            Hotkey::FrameAdvance => pacing.advance(),
+                            Hotkey::SlowMotion => pacing.toggle_slow_motion(),
+                            Hotkey::FastForward => pacing.set_fast_forward(true),
+                            Hotkey::CycleOverlay => {
+                                osd.overlay = osd.overlay.next();
+                                osd.message(&format!("Overlay: {}", osd.overlay));
+                            },
+                            Hotkey::Help => {
+                                if showing_help {
+                                    showing_help = false;
+                                    // unless F6 already carried on
+                                    if paused_by_help && pacing.is_paused() {
+                                        pacing.toggle_pause();
+                                    }
+                                    paused_by_help = false;
+                                } else if chip8.rom.is_some() {
+                                    // the game waits while it's read, as it does for the menu
+                                    showing_help = true;
+                                    paused_by_help = !pacing.is_paused();
+                                    if paused_by_help {
+                                        pacing.toggle_pause();
+                                    }
+                                } else {
+                                    osd.message("No help for this ROM");
+                                }
+                            }
+                        }
+                    }
+                    else if let (Some(key), Some(source)) = (keymap.lookup(scancode, keycode), KeySource::keyboard(scancode, keycode)) {
+                        if held_keys.press(source, key) {
+                            chip8.key_down(key);
                         }
                     }
                 },
                 Event::KeyUp {
-                    keycode: Some(key), ..
+                    keycode, scancode, ..
                 } => {
-                    if keybindings.contains_key(&key) {
-                        chip8.keys[keybindings[&key]] = false;
+                    if keycode.and_then(hotkey_for) == Some(Hotkey::FastForward) {
+                        pacing.set_fast_forward(false);
+                    }
+                    else if let Some(key) = KeySource::keyboard(scancode, keycode).and_then(|source| held_keys.release(source)) {
+                        chip8.key_up(key);
+                    }
+                },
+                Event::ControllerButtonDown { which, button, .. } => {
+                    if let Some(key) = padmap.lookup(button) {
+                        if held_keys.press(KeySource::Pad(which, button), key) {
+                            chip8.key_down(key);
+                        }
                     }
                 },
+                Event::ControllerButtonUp { which, button, .. } => {
+                    if let Some(key) = held_keys.release(KeySource::Pad(which, button)) {
+                        chip8.key_up(key);
+                    }
+                },
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } => {
+                    if let Some(key) = keypad.as_mut().and_then(|keypad| keypad.mouse_down(x, y)) {
+                        if held_keys.press(KeySource::Keypad, key) {
+                            chip8.key_down(key);
+                        }
+                    }
+                },
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } => {
+                    let clicked = keypad.as_mut().and_then(|keypad| keypad.mouse_up());
+                    if let Some(key) = clicked.and_then(|_| held_keys.release(KeySource::Keypad)) {
+                        chip8.key_up(key);
+                    }
+                },
+                Event::ControllerDeviceAdded { .. } | Event::ControllerDeviceRemoved { .. } => {
+                    gamepads.handle_event(&event);
+                },
                 _ => {}
             }
         }
 
-        // Since we try to sleep for 60hz, just assume we can
-        // update timers with impunity.
-        if chip8.delay_timer > 0 {
-            chip8.delay_timer -= 1;
+        if let Some(path) = rom_to_load {
+            let path_text = path.to_string_lossy().into_owned();
+            let loaded = read_program(&path_text)
+                .and_then(|bytes| start_program(&bytes, &memory_layout, &font, &current.quirks, seed, None).map(|started| (bytes, started)));
+            if let Ok((bytes, started)) = loaded {
+                program_path = path_text;
+                program = bytes;
+                chip8 = started;
+                start_address = None;
+                game = rom_hash(&program);
+                game_changed = true;
+
+                if padmap_path.is_none() {
+                    padmap = PadMap::for_program(&program_path).unwrap_or_default();
+                }
+                if let Some(ref mut sound) = sound {
+                    sound.lock().play_ticks(0, 1.0);
+                }
+                if watch_mode.is_some() {
+                    watcher = Some(FileWatcher::new(&path));
+                }
+                osd.message(&format!("Loaded {}", program_path));
+
+                browser = None;
+                menu = None;
+                showing_help = false;
+                if (paused_by_menu || paused_by_help) && pacing.is_paused() {
+                    pacing.toggle_pause();
+                    paused_by_menu = false;
+                    paused_by_help = false;
+                }
+            } else if let Err(e) = loaded {
+                osd.message(&format!("Could not load {}: {}", path.display(), e));
+            }
+        }
+
+        if let (Some(mode), Some(watcher)) = (watch_mode, watcher.as_mut()) {
+            if watcher.changed() {
+                let reloaded = read_program(&program_path).and_then(|bytes| {
+                    match mode {
+                        WatchMode::Reset => chip8 = start_program(&bytes, &memory_layout, &font, &current.quirks, seed, start_address)?,
+                        WatchMode::Restart => {
+                            chip8.restart(&bytes)?;
+                            if let Some(start) = start_address {
+                                chip8.program_counter = start;
+                            }
+                        }
+                    }
+                    Ok(bytes)
+                });
+                match reloaded {
+                    Ok(bytes) => {
+                        if let Some(ref mut sound) = sound {
+                            sound.lock().play_ticks(0, 1.0);
+                        }
+                        // a changed ROM is a different one as far as settings go
+                        game = rom_hash(&bytes);
+                        game_changed = true;
+                        program = bytes;
+                        osd.message(&format!("Reloaded {}", program_path));
+                    },
+                    // likely half written, so wait for the next change
+                    Err(e) => osd.message(&format!("Could not reload {}: {}", program_path, e))
+                }
+            }
+        }
+
+        // each game starts out with its own settings, if it has any
+        if game_changed {
+            current = settings_for(&settings, &options, game.as_deref());
+            palette_index = find_theme(&palettes, &current.palette).unwrap_or(palette_index);
+            current.palette = palettes[palette_index].name.clone();
+            match KeyMap::load(&current.keys) {
+                Ok(loaded) => keymap = loaded,
+                Err(e) => osd.message(&format!("Ignoring key mapping: {}", e))
+            }
+            match font_for(&current, &memory_layout) {
+                Ok(loaded) => font = loaded,
+                Err(e) => osd.message(&format!("Ignoring font: {}", e))
+            }
+            chip8.load_font(&font);
+            if let Some(ref mut sound) = sound {
+                sound.lock().set_tone(current.tone());
+            }
+            chip8.quirks = current.quirks.clone();
+        }
+
+        if pacing.status() != status || chip8.rom != rom {
+            canvas.window_mut().set_title(&window_title(chip8.rom, pacing.status())).unwrap();
+        }
+        if pacing.status() != status {
+
+            // a beep that's still going carries on at the new speed
+            if let Some(ref mut sound) = sound {
+                let ticks = if pacing.is_paused() { 0 } else { chip8.sound_timer };
+                sound.lock().play_ticks(ticks, pacing.speed());
+            }
+        }
+
+        osd.tick(Instant::now(), instructions_run);
+        let window_area = layout.window;
+        let registers = if osd.overlay == Overlay::Registers { chip8.register_lines() } else { Vec::new() };
+        osd.draw(&mut canvas, &palettes[palette_index], window_area, pacing.status(), registers);
+        // built each time, so a key mapping changed in the menu shows up
+        if let Some(rom) = chip8.rom.filter(|_| showing_help) {
+            draw_box(&mut canvas, &palettes[palette_index], window_area, Anchor::Centre, &help_lines(rom, &keymap, host_key_name));
         }
-        if chip8.sound_timer > 0 {
-            chip8.sound_timer -= 1;
+        if let Some(ref menu) = menu {
+            menu.draw(&mut canvas, &palettes[palette_index], window_area, &current, &palettes);
+        }
+        if let Some(ref mut browser) = browser {
+            browser.tick();
+            browser.draw(&mut canvas, &palettes[palette_index], window_area);
         }
 
         canvas.present();
@@ -785,12 +1715,30 @@ pub fn main() {
             ::std::thread::sleep(Duration::from_millis((1000 / 60) - ms_this_frame as u64));
         }
     }
+
+    if let Some((path, recorder)) = gif {
+        println!("{}", stop_recording(&path, recorder));
+    }
+
+    if let Some(wav) = recorder {
+        if let Err(e) = wav.finish() {
+            println!("Could not finish the sound recording: {}", e);
+        }
+    }
 }
 
 #[cfg(test)]
 mod computer_tests {
     use ComputerState;
     use opcodes::Chip8Opcode;
+    use loader::{read_from, read_program, LoadError};
+    use memory::{MemoryLayout, StackDepth};
+    use fault::Fault;
+    use cli::Options;
+    use hexfont::{get_hex_font, Font};
+    use settings::Settings;
+    use {font_for, layout_for, state_path};
+    use std::fs;
 
     fn top_nibble(instruction: u16) -> u8 {
         ((instruction & 0xf000) >> 8) as u8
@@ -851,6 +1799,7 @@ mod computer_tests {
         assert_eq!(test_decode(0xfa18), Chip8Opcode::SetSoundTimer(0xa));
         assert_eq!(test_decode(0xfa1e), Chip8Opcode::AddToIndexRegister(0xa));
         assert_eq!(test_decode(0xfa29), Chip8Opcode::UseSprite(0xa));
+        assert_eq!(test_decode(0xf330), Chip8Opcode::UseBigSprite(0x3));
         assert_eq!(test_decode(0xfa33), Chip8Opcode::ReadRegisterAsBCD(0xa));
         assert_eq!(test_decode(0xfa55), Chip8Opcode::DumpRegisters(0xa));
         assert_eq!(test_decode(0xfa65), Chip8Opcode::FillRegisters(0xa));
@@ -914,6 +1863,39 @@ mod computer_tests {
         assert_eq!(computer.program_counter, original_pc);
     }
 
+    #[test]
+    fn the_stack_only_goes_so_deep() {
+        let mut computer = ComputerState::with_layout(MemoryLayout::default().with_vip_areas().unwrap());
+        computer.program_counter = 0x202;
+        for _ in 0..12 {
+            computer.execute(Chip8Opcode::CallSub(0x202));
+        }
+        assert!(computer.fault.is_none());
+        computer.execute(Chip8Opcode::CallSub(0x202));
+        assert_eq!(computer.fault, Some(Fault::StackOverflow { address: 0x200, depth: 12 }));
+        assert_eq!(computer.stack.len(), 12);
+        assert!(computer.is_blocked());
+
+        // starting again clears it
+        computer.restart(&[0x00, 0xe0]).unwrap();
+        assert!(computer.fault.is_none());
+
+        let mut computer = ComputerState::with_layout(MemoryLayout { stack_depth: StackDepth::Unlimited, ..MemoryLayout::default() });
+        for _ in 0..1000 {
+            computer.execute(Chip8Opcode::CallSub(0x200));
+        }
+        assert!(computer.fault.is_none());
+    }
+
+    #[test]
+    fn returning_from_nowhere_is_a_fault() {
+        let mut computer = new_test_emulator();
+        computer.program_counter = 0x202;
+        computer.execute(Chip8Opcode::ReturnFromSubroutine);
+        assert_eq!(computer.fault, Some(Fault::StackUnderflow { address: 0x200 }));
+        assert_eq!(computer.program_counter, 0x200);
+    }
+
     #[test]
     fn skip_next_if_equal_works() {
         let mut computer = new_test_emulator();
@@ -1179,6 +2161,91 @@ mod computer_tests {
         assert_eq!(computer.waiting_for_keypress_target, 0xc); // should have the right register set
     }
 
+    #[test]
+    fn display_wait_blocks_until_vblank() {
+        let mut computer = new_test_emulator();
+        computer.execute(Chip8Opcode::Draw(0, 0, 1));
+        assert!(!computer.is_blocked()); // off by default
+
+        computer.quirks.display_wait = true;
+        computer.execute(Chip8Opcode::Draw(0, 0, 1));
+        assert!(computer.is_blocked());
+
+        computer.vblank();
+        assert!(!computer.is_blocked());
+    }
+
+    #[test]
+    fn vblank_counts_down_timers() {
+        let mut computer = new_test_emulator();
+        computer.delay_timer = 2;
+        computer.sound_timer = 1;
+
+        computer.vblank();
+        assert_eq!(computer.delay_timer, 1);
+        assert_eq!(computer.sound_timer, 0);
+
+        // timers stop at zero
+        computer.vblank();
+        assert_eq!(computer.delay_timer, 0);
+        assert_eq!(computer.sound_timer, 0);
+    }
+
+    #[test]
+    fn block_on_keypress_finishes_on_release() {
+        let mut computer = new_test_emulator();
+        computer.execute(Chip8Opcode::BlockOnKeyPress(0xc));
+
+        computer.key_down(0x7);
+        assert!(computer.is_blocked()); // still held
+        assert!(computer.keys[0x7]);
+
+        computer.key_down(0x3); // a second key doesn't steal the wait
+        computer.key_up(0x3);
+        assert!(computer.is_blocked());
+
+        computer.key_up(0x7);
+        assert!(!computer.is_blocked());
+        assert_eq!(computer.get_register(0xc), 0x7);
+    }
+
+    #[test]
+    fn block_on_keypress_ignores_keys_already_held() {
+        let mut computer = new_test_emulator();
+        computer.key_down(0x1);
+        computer.execute(Chip8Opcode::BlockOnKeyPress(0));
+
+        computer.key_up(0x1);
+        assert!(computer.is_blocked());
+    }
+
+    #[test]
+    fn block_on_keypress_can_finish_on_press() {
+        let mut computer = new_test_emulator();
+        computer.quirks.key_wait_on_press = true;
+        computer.execute(Chip8Opcode::BlockOnKeyPress(0x2));
+
+        computer.key_down(0xa);
+        assert!(!computer.is_blocked());
+        assert_eq!(computer.get_register(0x2), 0xa);
+    }
+
+    #[test]
+    fn blocked_computer_does_not_step_but_timers_run() {
+        let mut computer = new_test_emulator();
+        computer.memory[0x200] = 0x60; // 6005, v0 = 5
+        computer.memory[0x201] = 0x05;
+        computer.delay_timer = 10;
+
+        computer.execute(Chip8Opcode::BlockOnKeyPress(1));
+        computer.step();
+        assert_eq!(computer.program_counter, 0x200);
+        assert_eq!(computer.get_register(0), 0);
+
+        computer.vblank();
+        assert_eq!(computer.delay_timer, 9);
+    }
+
     #[test]
     fn set_index_register_works() {
         let mut computer = new_test_emulator();
@@ -1212,6 +2279,21 @@ mod computer_tests {
         assert_eq!(computer.get_register(0), 124)
     }
 
+    #[test]
+    fn set_sound_timer_is_reported_once() {
+        let mut computer = new_test_emulator();
+        assert_eq!(computer.take_sound_timer_write(), None);
+
+        computer.set_register(0, 30);
+        computer.execute(Chip8Opcode::SetSoundTimer(0));
+        assert_eq!(computer.take_sound_timer_write(), Some(30));
+        assert_eq!(computer.take_sound_timer_write(), None);
+
+        // counting down isn't a write
+        computer.vblank();
+        assert_eq!(computer.take_sound_timer_write(), None);
+    }
+
     #[test]
     fn register_register_or_works() {
         let mut computer = new_test_emulator();
@@ -1329,4 +2411,186 @@ mod computer_tests {
         assert_eq!(computer.memory[0x201], 103);
         assert_eq!(computer.memory[0x202], 66);
     }
+
+    #[test]
+    fn seeded_machines_roll_the_same_numbers() {
+        let mut first = new_test_emulator();
+        let mut second = new_test_emulator();
+        first.seed(42);
+        second.seed(42);
+        for _ in 0..8 {
+            first.execute(Chip8Opcode::Random(0, 0xff));
+            second.execute(Chip8Opcode::Random(0, 0xff));
+            assert_eq!(first.get_register(0), second.get_register(0));
+        }
+    }
+
+    #[test]
+    fn restart_runs_the_program_again() {
+        let mut computer = new_test_emulator();
+        computer.load_bytes(&fs::read("roms/c8games/MAZE").unwrap()).unwrap();
+        computer.memory[0x400] = 0xff;
+        computer.program_counter = 0x21a;
+        computer.stack.push(0x204);
+        computer.registers[3] = 9;
+        computer.gfx[10] = 1;
+
+        computer.restart(&fs::read("roms/c8games/PONG").unwrap()).unwrap();
+        assert_eq!(computer.program_counter, 0x200);
+        assert!(computer.stack.is_empty());
+        assert_eq!(computer.memory[0x200..0x202], [0x6a, 0x02]);
+        assert_eq!(computer.memory[0x400], 0);
+        // the rest carries on
+        assert_eq!(computer.registers[3], 9);
+        assert_eq!(computer.gfx[10], 1);
+    }
+
+    #[test]
+    fn programs_load_from_anywhere_if_they_fit() {
+        let mut computer = new_test_emulator();
+        computer.load_bytes(&read_from(&[0x00u8, 0xe0][..]).unwrap()).unwrap();
+        assert_eq!(computer.memory[0x200..0x202], [0x00, 0xe0]);
+        assert!(computer.rom.is_none());
+
+        computer.load_bytes(&read_program("roms/c8games/PONG").unwrap()).unwrap();
+        assert_eq!(computer.rom.unwrap().title, "Pong");
+
+        assert_eq!(read_program("no/such/ROM").and_then(|program| computer.load_bytes(&program)), Err(LoadError::NotFound("no/such/ROM".to_string())));
+        assert_eq!(computer.load_bytes(&[]), Err(LoadError::Empty));
+        assert_eq!(computer.load_bytes(&[0; 4000]), Err(LoadError::TooLarge { size: 4000, room: 0xe00 }));
+        assert_eq!(computer.restart(&[0; 4000]), Err(LoadError::TooLarge { size: 4000, room: 0xe00 }));
+        // nothing changes when a program doesn't fit
+        assert_eq!(computer.rom.unwrap().title, "Pong");
+    }
+
+    #[test]
+    fn machines_can_be_laid_out_differently() {
+        // an ETI-660, with the font where later interpreters put it
+        let mut computer = ComputerState::with_layout(MemoryLayout::new(0x600, 0x050, 4096).unwrap());
+        computer.load_bytes(&[0xf0, 0x29]).unwrap();
+        assert_eq!(computer.program_counter, 0x600);
+        assert_eq!(computer.memory[0x050..0x055], get_hex_font()[..5]);
+        assert_eq!(computer.memory[0x000], 0);
+        computer.step();
+        assert_eq!(computer.index, 0x050);
+        assert_eq!(computer.load_bytes(&[0; 0xa01]), Err(LoadError::TooLarge { size: 0xa01, room: 0xa00 }));
+
+        // on a 2K machine, I wraps around to the bottom of memory
+        let mut computer = ComputerState::with_layout(MemoryLayout::new(0x200, 0x000, 2048).unwrap());
+        computer.index = 0x7ff;
+        computer.set_register(0, 1);
+        computer.set_register(1, 2);
+        computer.execute(Chip8Opcode::DumpRegisters(1));
+        assert_eq!(computer.memory[0x7ff], 1);
+        assert_eq!(computer.memory[0x000], 2);
+
+        // and at the top of 64K, so do the PC and I
+        let mut computer = ComputerState::with_layout(MemoryLayout::new(0x200, 0x000, 65536).unwrap());
+        computer.memory[0xfffc..].copy_from_slice(&[0x30, 0x00, 0x00, 0xe0]);
+        computer.program_counter = 0xfffc;
+        computer.step();
+        assert_eq!(computer.program_counter, 0x0000);
+        computer.index = 0xffff;
+        computer.set_register(0, 2);
+        computer.execute(Chip8Opcode::AddToIndexRegister(0));
+        assert_eq!(computer.index, 0x0001);
+    }
+
+    #[test]
+    fn fonts_can_be_swapped() {
+        let mut computer = new_test_emulator();
+        computer.load_font(&Font::builtin("schip").unwrap());
+        computer.set_register(0, 3);
+        computer.execute(Chip8Opcode::UseBigSprite(0));
+        assert_eq!(computer.index, 80 + 3 * 10);
+        assert_eq!(computer.memory[110..120], [0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c]);
+
+        computer.load_font(&Font::builtin("vip").unwrap());
+        computer.set_register(0, 7);
+        computer.execute(Chip8Opcode::UseSprite(0));
+        assert_eq!(computer.memory[computer.index as usize + 4], 0x10);
+        // without big digits, the old ones are gone
+        assert_eq!(computer.memory[110..120], [0; 10]);
+
+        // big digits need room below the program
+        let settings = Settings { font: "octo".to_string(), ..Settings::default() };
+        let cramped = MemoryLayout::new(0x200, 0x1a0, 4096).unwrap();
+        assert!(font_for(&settings, &cramped).unwrap_err().contains("overwritten"));
+        assert_eq!(font_for(&settings, &MemoryLayout::default()), Font::builtin("octo").ok_or(String::new()));
+    }
+
+    #[test]
+    fn the_vip_keeps_its_stack_and_display_in_memory() {
+        let mut computer = ComputerState::with_layout(MemoryLayout::default().with_vip_areas().unwrap());
+        computer.program_counter = 0x202;
+        computer.execute(Chip8Opcode::CallSub(0x300));
+        assert_eq!(computer.memory[0xea0..0xea2], [0x02, 0x02]);
+
+        // a program can change where it returns to
+        computer.index = 0xea1;
+        computer.set_register(0, 0x40);
+        computer.execute(Chip8Opcode::DumpRegisters(0));
+        assert_eq!(computer.stack, vec![0x240]);
+
+        // drawing shows up in memory a bit a pixel, and writing there draws
+        computer.index = 0x000;
+        computer.set_register(0, 8);
+        computer.set_register(1, 1);
+        computer.execute(Chip8Opcode::Draw(0, 1, 1));
+        assert_eq!(computer.memory[0xf09], 0xf0);
+        computer.index = 0xf00;
+        computer.set_register(0, 0x81);
+        computer.execute(Chip8Opcode::DumpRegisters(0));
+        assert_eq!(computer.gfx[0..8], [1, 0, 0, 0, 0, 0, 0, 1]);
+        computer.execute(Chip8Opcode::DisplayClear);
+        assert!(computer.memory[0xf00..].iter().all(|byte| *byte == 0));
+
+        // and there's less room for programs
+        assert_eq!(computer.load_bytes(&[0; 0xca1]), Err(LoadError::TooLarge { size: 0xca1, room: 0xca0 }));
+    }
+
+    #[test]
+    fn layouts_come_from_the_command_line() {
+        assert_eq!(layout_for(&Options::default()), Ok(MemoryLayout::default()));
+        let options = Options { load_address: Some(0x600), memory_size: Some(65536), ..Options::default() };
+        assert_eq!(layout_for(&options).unwrap().program_room(), 65536 - 0x600);
+        let options = Options { start: Some(0x900), memory_size: Some(2048), ..Options::default() };
+        assert_eq!(layout_for(&options).unwrap_err(), "--start 0x900 is past the end of 2048 bytes of memory");
+        let options = Options { vip_memory: true, ..Options::default() };
+        assert!(layout_for(&options).unwrap().vip_areas);
+        let options = Options { vip_memory: true, stack_depth: Some(StackDepth::Unlimited), ..Options::default() };
+        assert!(layout_for(&options).unwrap_err().contains("12 calls deep"));
+        let options = Options { vip_memory: true, stack_depth: Some(StackDepth::VIP), ..Options::default() };
+        assert_eq!(layout_for(&options).unwrap().stack_depth, StackDepth::VIP);
+    }
+
+    #[test]
+    fn register_lines_works() {
+        let mut computer = new_test_emulator();
+        computer.program_counter = 0x2a4;
+        computer.registers[0xb] = 0x7f;
+        computer.delay_timer = 30;
+
+        let lines = computer.register_lines();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "PC 2A4  I 000");
+        assert_eq!(lines[3], "V8 00  V9 00  VA 00  VB 7F");
+        assert_eq!(lines[5], "DT 1E  ST 00  SP 0");
+
+        // the innermost calls are listed for debugging
+        for address in 0..10 {
+            computer.stack.push(0x200 + address * 2);
+        }
+        let lines = computer.register_lines();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[6], "S2 204  S3 206  S4 208  S5 20A");
+        assert_eq!(lines[7], "S6 20C  S7 20E  S8 210  S9 212");
+    }
+
+    #[test]
+    fn states_need_a_game_to_go_with() {
+        assert_eq!(state_path("roms/PONG").unwrap(), "roms/PONG.state");
+        assert!(state_path("").is_err());
+        assert!(state_path("-").is_err());
+    }
 }
@@ -0,0 +1,181 @@
+use asm::parse_number;
+
+// The built-in font: 16 hex digits of 5 bytes each
+pub const FONT_SIZE: usize = 16 * 5;
+
+// Where the COSMAC VIP kept its call stack, an address of 2 bytes for
+// each of 12 levels, and its display, a bit for each pixel
+pub const VIP_STACK: u16 = 0xea0;
+pub const VIP_DISPLAY: u16 = 0xf00;
+pub const VIP_DISPLAY_SIZE: usize = 64 * 32 / 8;
+
+// How many calls deep a program can go before the machine faults
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackDepth {
+    Limit(usize),
+    // Runaway recursion carries on, to watch it in a debugger
+    Unlimited
+}
+
+impl StackDepth {
+    // The VIP had room for 12 return addresses, SCHIP for 16
+    pub const VIP: StackDepth = StackDepth::Limit(12);
+    pub const SCHIP: StackDepth = StackDepth::Limit(16);
+
+    pub fn parse(text: &str) -> Result<StackDepth, String> {
+        match text {
+            "unlimited" => Ok(StackDepth::Unlimited),
+            _ => match text.parse::<usize>() {
+                Ok(depth) if depth > 0 => Ok(StackDepth::Limit(depth)),
+                _ => Err(format!("'{}' is not a stack depth, e.g. 12, 16 or unlimited", text))
+            }
+        }
+    }
+
+    // Whether there's room for another call with `depth` already on the stack
+    pub fn has_room(&self, depth: usize) -> bool {
+        match *self {
+            StackDepth::Limit(limit) => depth < limit,
+            StackDepth::Unlimited => true
+        }
+    }
+}
+
+// Where things go in memory, which differed from machine to machine.
+// The COSMAC VIP loaded programs at 0x200, the ETI-660 at 0x600, and
+// many later interpreters kept the font at 0x050.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryLayout {
+    // Where programs are loaded, and start running
+    pub load_address: u16,
+    pub font_address: u16,
+    // In bytes
+    pub size: usize,
+    // The stack and display are in memory, where the VIP had them,
+    // rather than off to the side
+    pub vip_areas: bool,
+    pub stack_depth: StackDepth
+}
+
+impl Default for MemoryLayout {
+    fn default() -> MemoryLayout {
+        MemoryLayout {
+            load_address: 0x200,
+            font_address: 0x000,
+            size: 4096,
+            vip_areas: false,
+            stack_depth: StackDepth::SCHIP
+        }
+    }
+}
+
+impl MemoryLayout {
+    // Checks the font fits below where programs go
+    pub fn new(load_address: u16, font_address: u16, size: usize) -> Result<MemoryLayout, String> {
+        if load_address as usize >= size {
+            return Err(format!("Programs can't load at 0x{:03X} with only {} bytes of memory", load_address, size));
+        }
+        let layout = MemoryLayout { load_address, font_address, size, ..MemoryLayout::default() };
+        layout.check_font(FONT_SIZE)?;
+        Ok(layout)
+    }
+
+    // Whether a font this many bytes long fits, as some have big digits too
+    pub fn check_font(&self, font_size: usize) -> Result<(), String> {
+        if self.font_address as usize + font_size > self.load_address as usize {
+            return Err(format!("The font at 0x{:03X} would be overwritten by programs loaded at 0x{:03X}", self.font_address, self.load_address));
+        }
+        Ok(())
+    }
+
+    // The same, with the stack and display where the VIP kept them, and
+    // its stack depth
+    pub fn with_vip_areas(self) -> Result<MemoryLayout, String> {
+        if self.size < 4096 {
+            return Err(format!("The VIP's stack and display need 4096 bytes of memory, not {}", self.size));
+        }
+        if self.load_address >= VIP_STACK {
+            return Err(format!("Programs loaded at 0x{:03X} would be in the VIP's stack", self.load_address));
+        }
+        Ok(MemoryLayout { vip_areas: true, stack_depth: StackDepth::VIP, ..self })
+    }
+
+    // The VIP's stack has room for 12 calls and no more, as the display
+    // comes straight after it
+    pub fn with_stack_depth(self, depth: StackDepth) -> Result<MemoryLayout, String> {
+        if self.vip_areas && depth != StackDepth::VIP {
+            return Err("The VIP's stack is 12 calls deep, whatever --stack says".to_string());
+        }
+        Ok(MemoryLayout { stack_depth: depth, ..self })
+    }
+
+    // How big a program can be
+    pub fn program_room(&self) -> usize {
+        let end = if self.vip_areas { VIP_STACK as usize } else { self.size };
+        end - self.load_address as usize
+    }
+}
+
+// Memory sizes as written on the command line: 2K, 4K, 64K or a number of bytes
+pub fn parse_memory_size(text: &str) -> Result<usize, String> {
+    let upper = text.to_uppercase();
+    let size = match upper.strip_suffix('K') {
+        Some(kilobytes) => kilobytes.parse::<usize>().ok().map(|k| k * 1024),
+        None => parse_number(text).map(|bytes| bytes as usize)
+    };
+    match size {
+        // enough for the font and a program, and no more than 16 bits can address
+        Some(size) if (0x400..=0x10000).contains(&size) => Ok(size),
+        _ => Err(format!("'{}' is not a memory size from 1K to 64K, e.g. 2K or 4K", text))
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(parse_memory_size("2K"), Ok(2048));
+        assert_eq!(parse_memory_size("64k"), Ok(65536));
+        assert_eq!(parse_memory_size("0x1000"), Ok(4096));
+        assert!(parse_memory_size("128K").is_err());
+        assert!(parse_memory_size("lots").is_err());
+    }
+
+    #[test]
+    fn stack_depths_parse() {
+        assert_eq!(StackDepth::parse("12"), Ok(StackDepth::VIP));
+        assert_eq!(StackDepth::parse("unlimited"), Ok(StackDepth::Unlimited));
+        assert!(StackDepth::parse("0").is_err());
+        assert!(StackDepth::SCHIP.has_room(15));
+        assert!(!StackDepth::SCHIP.has_room(16));
+        assert!(StackDepth::Unlimited.has_room(100_000));
+    }
+
+    #[test]
+    fn layouts_must_fit_together() {
+        assert_eq!(MemoryLayout::new(0x200, 0x000, 4096), Ok(MemoryLayout::default()));
+        assert_eq!(MemoryLayout::new(0x600, 0x050, 4096).unwrap().program_room(), 0xa00);
+        assert!(MemoryLayout::new(0x800, 0x000, 2048).unwrap_err().contains("only 2048 bytes"));
+        assert!(MemoryLayout::new(0x200, 0x1e0, 4096).unwrap_err().contains("overwritten"));
+        assert!(MemoryLayout::new(0x200, 0xf00, 4096).is_err());
+        // big digits need more room
+        assert!(MemoryLayout::new(0x200, 0x1a0, 4096).unwrap().check_font(FONT_SIZE + 100).is_err());
+    }
+
+    #[test]
+    fn the_vip_areas_leave_less_room() {
+        let vip = MemoryLayout::default().with_vip_areas().unwrap();
+        assert_eq!(vip.program_room(), 0xea0 - 0x200);
+        assert_eq!(vip.stack_depth, StackDepth::Limit(12));
+        assert!(MemoryLayout::new(0x200, 0x000, 2048).unwrap().with_vip_areas().unwrap_err().contains("need 4096 bytes"));
+        assert!(MemoryLayout::new(0xf00, 0x000, 65536).unwrap().with_vip_areas().is_err());
+
+        // any deeper and the stack would run into the display
+        assert!(vip.clone().with_stack_depth(StackDepth::VIP).is_ok());
+        assert!(vip.clone().with_stack_depth(StackDepth::Limit(49)).is_err());
+        assert!(vip.with_stack_depth(StackDepth::Unlimited).is_err());
+        assert_eq!(MemoryLayout::default().with_stack_depth(StackDepth::Unlimited).unwrap().stack_depth, StackDepth::Unlimited);
+    }
+}
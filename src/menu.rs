@@ -0,0 +1,271 @@
+use sdl2::keyboard::Keycode;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget};
+use std::path::Path;
+use keymap::PRESETS;
+use osd::{draw_box, Anchor};
+use palette::{find_theme, Palette};
+use settings::Settings;
+
+// Instructions per frame to step through. The COSMAC VIP managed
+// around 10, later interpreters anything up to 1000.
+const SPEEDS: [u32; 14] = [1, 2, 3, 5, 7, 10, 15, 20, 30, 50, 100, 200, 500, 1000];
+
+#[derive(Clone, Debug, PartialEq)]
+enum MenuEntry {
+    Resume,
+    Speed,
+    Palette,
+    DisplayWait,
+    KeyWait,
+    Keys,
+    Volume,
+    SaveForGame,
+    LoadRom,
+    SaveState,
+    LoadState,
+    Quit
+}
+
+const MAIN_ENTRIES: [MenuEntry; 12] = [
+    MenuEntry::Resume, MenuEntry::Speed, MenuEntry::Palette, MenuEntry::DisplayWait,
+    MenuEntry::KeyWait, MenuEntry::Keys, MenuEntry::Volume, MenuEntry::SaveForGame,
+    MenuEntry::LoadRom, MenuEntry::SaveState, MenuEntry::LoadState, MenuEntry::Quit
+];
+
+// What the emulator needs to do after a key press in the menu
+#[derive(Clone, Debug, PartialEq)]
+pub enum MenuAction {
+    Nothing,
+    Close,
+    // Something in the settings was changed, so apply and save them
+    SettingsChanged,
+    // Keep the settings as they are for the game that's loaded
+    SaveForGame,
+    SaveState,
+    LoadState,
+    // Open the ROM browser
+    BrowseRoms,
+    Quit
+}
+
+// The pause menu, drawn over the game and driven by the arrow keys,
+// Enter and Escape
+pub struct Menu {
+    entries: Vec<MenuEntry>,
+    selected: usize
+}
+
+impl Default for Menu {
+    fn default() -> Menu {
+        Menu {
+            entries: MAIN_ENTRIES.to_vec(),
+            selected: 0
+        }
+    }
+}
+
+impl Menu {
+    pub fn key_down(&mut self, key: Keycode, settings: &mut Settings, palettes: &[Palette]) -> MenuAction {
+        let count = self.entries.len();
+        match key {
+            Keycode::Up if count > 0 => {
+                self.selected = (self.selected + count - 1) % count;
+                MenuAction::Nothing
+            },
+            Keycode::Down if count > 0 => {
+                self.selected = (self.selected + 1) % count;
+                MenuAction::Nothing
+            },
+            Keycode::Left | Keycode::Right => {
+                let direction = if key == Keycode::Left { -1 } else { 1 };
+                match self.entries.get(self.selected) {
+                    Some(entry) if adjust(settings, entry, direction, palettes) => MenuAction::SettingsChanged,
+                    _ => MenuAction::Nothing
+                }
+            },
+            Keycode::Return | Keycode::KpEnter | Keycode::Space => {
+                let entry = match self.entries.get(self.selected) {
+                    Some(entry) => entry.clone(),
+                    None => return MenuAction::Nothing
+                };
+                // settings step forwards, so they can be changed with just Enter
+                if adjust(settings, &entry, 1, palettes) {
+                    return MenuAction::SettingsChanged;
+                }
+                match entry {
+                    MenuEntry::Resume => MenuAction::Close,
+                    MenuEntry::SaveForGame => MenuAction::SaveForGame,
+                    MenuEntry::LoadRom => MenuAction::BrowseRoms,
+                    MenuEntry::SaveState => MenuAction::SaveState,
+                    MenuEntry::LoadState => MenuAction::LoadState,
+                    MenuEntry::Quit => MenuAction::Quit,
+                    _ => MenuAction::Nothing
+                }
+            },
+            Keycode::Escape | Keycode::Backspace => MenuAction::Close,
+            _ => MenuAction::Nothing
+        }
+    }
+
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, palette: &Palette, area: Rect,
+                                 settings: &Settings, palettes: &[Palette]) {
+        let mut lines = vec!["Paused".to_string(), String::new()];
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let pointer = if index == self.selected { ">" } else { " " };
+            let line = match value(settings, entry, palettes) {
+                Some(value) => format!("{} {:<14}< {} >", pointer, label(entry), value),
+                None => format!("{} {}", pointer, label(entry))
+            };
+            lines.push(line);
+        }
+
+        draw_box(canvas, palette, area, Anchor::Centre, &lines);
+    }
+}
+
+fn label(entry: &MenuEntry) -> String {
+    match *entry {
+        MenuEntry::Resume => "Resume".to_string(),
+        MenuEntry::Speed => "Speed".to_string(),
+        MenuEntry::Palette => "Palette".to_string(),
+        MenuEntry::DisplayWait => "Display wait".to_string(),
+        MenuEntry::KeyWait => "Key wait".to_string(),
+        MenuEntry::Keys => "Keys".to_string(),
+        MenuEntry::Volume => "Volume".to_string(),
+        MenuEntry::SaveForGame => "Save for this game".to_string(),
+        MenuEntry::LoadRom => "Load ROM...".to_string(),
+        MenuEntry::SaveState => "Save state".to_string(),
+        MenuEntry::LoadState => "Load state".to_string(),
+        MenuEntry::Quit => "Quit".to_string()
+    }
+}
+
+// What a setting is set to, for entries that are settings
+fn value(settings: &Settings, entry: &MenuEntry, palettes: &[Palette]) -> Option<String> {
+    match *entry {
+        MenuEntry::Speed => Some(format!("{} per frame", settings.instructions_per_frame)),
+        MenuEntry::Palette => {
+            let index = find_theme(palettes, &settings.palette).unwrap_or(0);
+            Some(palettes[index].name.clone())
+        },
+        MenuEntry::DisplayWait => Some(if settings.quirks.display_wait { "on" } else { "off" }.to_string()),
+        MenuEntry::KeyWait => Some(if settings.quirks.key_wait_on_press { "press" } else { "release" }.to_string()),
+        MenuEntry::Keys => Some(if PRESETS.contains(&settings.keys.as_str()) {
+            settings.keys.clone()
+        } else {
+            let name = Path::new(&settings.keys).file_name().map(|name| name.to_string_lossy().into_owned());
+            format!("file {}", name.unwrap_or_default())
+        }),
+        MenuEntry::Volume => Some(format!("{}%", (settings.volume * 100.0).round())),
+        _ => None
+    }
+}
+
+// Steps a setting to its next (1) or previous (-1) choice.
+// False if the entry isn't a setting.
+fn adjust(settings: &mut Settings, entry: &MenuEntry, direction: i32, palettes: &[Palette]) -> bool {
+    match *entry {
+        MenuEntry::Speed => {
+            let current = settings.instructions_per_frame;
+            let next = if direction > 0 {
+                SPEEDS.iter().cloned().find(|&speed| speed > current)
+            } else {
+                SPEEDS.iter().cloned().rev().find(|&speed| speed < current)
+            };
+            settings.instructions_per_frame = next.unwrap_or(current);
+        },
+        MenuEntry::Palette => {
+            let count = palettes.len() as i32;
+            let index = find_theme(palettes, &settings.palette).unwrap_or(0) as i32;
+            settings.palette = palettes[((index + direction + count) % count) as usize].name.clone();
+        },
+        MenuEntry::DisplayWait => settings.quirks.display_wait = !settings.quirks.display_wait,
+        MenuEntry::KeyWait => settings.quirks.key_wait_on_press = !settings.quirks.key_wait_on_press,
+        MenuEntry::Keys => {
+            let count = PRESETS.len() as i32;
+            // a key mapping file counts as coming just before the first preset
+            let index = match PRESETS.iter().position(|preset| *preset == settings.keys) {
+                Some(index) => index as i32,
+                None => if direction > 0 { -1 } else { 0 }
+            };
+            settings.keys = PRESETS[((index + direction + count) % count) as usize].to_string();
+        },
+        MenuEntry::Volume => {
+            let steps = (settings.volume * 10.0).round() as i32 + direction;
+            settings.volume = steps.clamp(0, 10) as f32 / 10.0;
+        },
+        _ => return false
+    }
+    true
+}
+
+#[cfg(test)]
+mod menu_tests {
+    use super::*;
+    use sdl2::pixels::Color;
+
+    fn test_palettes() -> Vec<Palette> {
+        vec![
+            Palette::new("default", Color::RGB(255, 255, 255), Color::RGB(0, 0, 0)),
+            Palette::new("green", Color::RGB(0, 255, 0), Color::RGB(0, 0, 0))
+        ]
+    }
+
+    fn select(menu: &mut Menu, entry: MenuEntry) {
+        menu.selected = menu.entries.iter().position(|e| *e == entry).unwrap();
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut menu = Menu::default();
+        let mut settings = Settings::default();
+        menu.key_down(Keycode::Up, &mut settings, &test_palettes());
+        assert_eq!(menu.entries[menu.selected], MenuEntry::Quit);
+        menu.key_down(Keycode::Down, &mut settings, &test_palettes());
+        assert_eq!(menu.entries[menu.selected], MenuEntry::Resume);
+    }
+
+    #[test]
+    fn settings_step_through_their_choices() {
+        let palettes = test_palettes();
+        let mut settings = Settings::default();
+
+        assert!(adjust(&mut settings, &MenuEntry::Speed, 1, &palettes));
+        assert_eq!(settings.instructions_per_frame, 2);
+        settings.instructions_per_frame = 12;
+        adjust(&mut settings, &MenuEntry::Speed, -1, &palettes);
+        assert_eq!(settings.instructions_per_frame, 10);
+
+        adjust(&mut settings, &MenuEntry::Palette, -1, &palettes);
+        assert_eq!(settings.palette, "green");
+
+        adjust(&mut settings, &MenuEntry::Volume, 1, &palettes);
+        assert_eq!(settings.volume, 0.4);
+
+        settings.keys = "my-keys.txt".to_string();
+        assert_eq!(value(&settings, &MenuEntry::Keys, &palettes), Some("file my-keys.txt".to_string()));
+        adjust(&mut settings, &MenuEntry::Keys, 1, &palettes);
+        assert_eq!(settings.keys, PRESETS[0]);
+
+        assert!(!adjust(&mut settings, &MenuEntry::Quit, 1, &palettes));
+    }
+
+    #[test]
+    fn enter_changes_settings_and_picks_actions() {
+        let palettes = test_palettes();
+        let mut settings = Settings::default();
+        let mut menu = Menu::default();
+
+        select(&mut menu, MenuEntry::DisplayWait);
+        assert_eq!(menu.key_down(Keycode::Return, &mut settings, &palettes), MenuAction::SettingsChanged);
+        assert!(settings.quirks.display_wait);
+
+        select(&mut menu, MenuEntry::SaveState);
+        assert_eq!(menu.key_down(Keycode::Return, &mut settings, &palettes), MenuAction::SaveState);
+        select(&mut menu, MenuEntry::SaveForGame);
+        assert_eq!(menu.key_down(Keycode::Return, &mut settings, &palettes), MenuAction::SaveForGame);
+        assert_eq!(menu.key_down(Keycode::Escape, &mut settings, &palettes), MenuAction::Close);
+    }
+}
@@ -36,6 +36,7 @@ pub enum Chip8Opcode {
     /* FX18 */ SetSoundTimer(Chip8Register),
     /* FX1E */ AddToIndexRegister(Chip8Register), // I += Vx
     /* FX29 */ UseSprite(Chip8Register), // I = sprites[Vx]
+    /* FX30 */ UseBigSprite(Chip8Register), // I = big_sprites[Vx], from SCHIP
     /* FX33 */ ReadRegisterAsBCD(Chip8Register), // store the BCD of Vx in I
     /* FX55 */ DumpRegisters(Chip8Register), // store V0...Vx in memory starting at I
     /* FX65 */ FillRegisters(Chip8Register), // read from I to V0...Vx
@@ -0,0 +1,238 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, RenderTarget};
+use std::fmt;
+use std::time::{Duration, Instant};
+use palette::Palette;
+use textfont::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+// Each pixel of the font becomes a square this big
+const TEXT_SCALE: u32 = 2;
+// Between characters and lines, in font pixels
+const SPACING: u32 = 1;
+// Around the text, in window pixels
+const PADDING: u32 = 6;
+const MARGIN: i32 = 8;
+
+// How long messages stay up, in frames
+const MESSAGE_FRAMES: u32 = 120;
+
+// What's drawn over the game, besides messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overlay {
+    Off,
+    // frames and instructions per second
+    Stats,
+    // the above, and the CPU registers
+    Registers
+}
+
+impl Overlay {
+    pub fn next(self) -> Overlay {
+        match self {
+            Overlay::Off => Overlay::Stats,
+            Overlay::Stats => Overlay::Registers,
+            Overlay::Registers => Overlay::Off
+        }
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Overlay::Off => "off",
+            Overlay::Stats => "stats",
+            Overlay::Registers => "registers"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Size in window pixels of some lines of text, as draw_text lays them out
+pub fn text_size(lines: &[String]) -> (u32, u32) {
+    let columns = lines.iter().map(|line| line.chars().count() as u32).max().unwrap_or(0);
+    let rows = lines.len() as u32;
+    let width = (columns * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING) * TEXT_SCALE;
+    let height = (rows * (GLYPH_HEIGHT + SPACING)).saturating_sub(SPACING) * TEXT_SCALE;
+    (width, height)
+}
+
+pub fn draw_text<T: RenderTarget>(canvas: &mut Canvas<T>, lines: &[String], x: i32, y: i32, color: Color) {
+    canvas.set_draw_color(color);
+    for (row, line) in lines.iter().enumerate() {
+        let line_y = y + (row as u32 * (GLYPH_HEIGHT + SPACING) * TEXT_SCALE) as i32;
+        for (column, c) in line.chars().enumerate() {
+            let char_x = x + (column as u32 * (GLYPH_WIDTH + SPACING) * TEXT_SCALE) as i32;
+            for (glyph_y, bits) in glyph(c).iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> glyph_x) != 0 {
+                        let pixel = Rect::new(
+                            char_x + (glyph_x * TEXT_SCALE) as i32,
+                            line_y + (glyph_y as u32 * TEXT_SCALE) as i32,
+                            TEXT_SCALE, TEXT_SCALE);
+                        canvas.fill_rect(pixel).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Where a box of text goes
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    Centre
+}
+
+// Text drawn over the game: short messages when something changes, a
+// frame rate counter and the CPU registers. Drawn with our own font, so
+// it needs nothing more than SDL itself.
+pub struct Osd {
+    message: Option<String>,
+    message_frames: u32,
+    pub overlay: Overlay,
+    // Counted since `since`, and turned into rates once a second
+    frames: u32,
+    instructions: u64,
+    since: Instant,
+    fps: f32,
+    ips: f32
+}
+
+impl Osd {
+    pub fn new(now: Instant) -> Osd {
+        Osd {
+            message: None,
+            message_frames: 0,
+            overlay: Overlay::Off,
+            frames: 0,
+            instructions: 0,
+            since: now,
+            fps: 0.0,
+            ips: 0.0
+        }
+    }
+
+    // Shows for a couple of seconds, replacing any message already up
+    pub fn message(&mut self, text: &str) {
+        self.message = Some(text.to_string());
+        self.message_frames = MESSAGE_FRAMES;
+    }
+
+    // Call once per frame on the host, with how many instructions ran in it
+    pub fn tick(&mut self, now: Instant, instructions: u64) {
+        if self.message_frames > 0 {
+            self.message_frames -= 1;
+            if self.message_frames == 0 {
+                self.message = None;
+            }
+        }
+
+        self.frames += 1;
+        self.instructions += instructions;
+
+        let elapsed = now.duration_since(self.since);
+        if elapsed >= Duration::from_secs(1) {
+            let seconds = elapsed.as_secs_f32();
+            self.fps = self.frames as f32 / seconds;
+            self.ips = self.instructions as f32 / seconds;
+            self.frames = 0;
+            self.instructions = 0;
+            self.since = now;
+        }
+    }
+
+    pub fn stats(&self) -> String {
+        format!("{:.0} FPS {:.0} IPS", self.fps, self.ips)
+    }
+
+    // `status` stays up for as long as it's given, e.g. while paused
+    pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>, palette: &Palette, area: Rect,
+                                 status: Option<String>, registers: Vec<String>) {
+        if let Some(status) = status {
+            draw_box(canvas, palette, area, Anchor::TopLeft, &[status]);
+        }
+
+        match self.overlay {
+            Overlay::Off => {},
+            Overlay::Stats => {
+                draw_box(canvas, palette, area, Anchor::TopRight, &[self.stats()]);
+            },
+            Overlay::Registers => {
+                let mut lines = vec![self.stats(), String::new()];
+                lines.extend(registers);
+                draw_box(canvas, palette, area, Anchor::TopRight, &lines);
+            }
+        }
+
+        if let Some(ref message) = self.message {
+            draw_box(canvas, palette, area, Anchor::BottomLeft, std::slice::from_ref(message));
+        }
+    }
+}
+
+// Text on a see-through panel, so it can be read over the game
+pub fn draw_box<T: RenderTarget>(canvas: &mut Canvas<T>, palette: &Palette, area: Rect, anchor: Anchor, lines: &[String]) {
+    let (text_width, text_height) = text_size(lines);
+    let (width, height) = (text_width + PADDING * 2, text_height + PADDING * 2);
+    let (x, y) = match anchor {
+        Anchor::TopLeft => (area.x() + MARGIN, area.y() + MARGIN),
+        Anchor::TopRight => (area.right() - MARGIN - width as i32, area.y() + MARGIN),
+        Anchor::BottomLeft => (area.x() + MARGIN, area.bottom() - MARGIN - height as i32),
+        Anchor::Centre => (area.x() + (area.width() - width) as i32 / 2, area.y() + (area.height() - height) as i32 / 2)
+    };
+
+    let background = palette.background;
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(background.r, background.g, background.b, 192));
+    canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    draw_text(canvas, lines, x + PADDING as i32, y + PADDING as i32, palette.foreground);
+}
+
+#[cfg(test)]
+mod osd_tests {
+    use super::*;
+
+    #[test]
+    fn text_size_works() {
+        assert_eq!(text_size(&[]), (0, 0));
+        // 5 pixels a character plus 1 between, all doubled
+        assert_eq!(text_size(&["AB".to_string()]), (22, 14));
+        assert_eq!(text_size(&["A".to_string(), "ABC".to_string()]), (34, 30));
+    }
+
+    #[test]
+    fn overlays_cycle() {
+        assert_eq!(Overlay::Off.next(), Overlay::Stats);
+        assert_eq!(Overlay::Stats.next(), Overlay::Registers);
+        assert_eq!(Overlay::Registers.next(), Overlay::Off);
+    }
+
+    #[test]
+    fn messages_go_away() {
+        let start = Instant::now();
+        let mut osd = Osd::new(start);
+        osd.message("Paused");
+        for _ in 1..MESSAGE_FRAMES {
+            osd.tick(start, 0);
+        }
+        assert_eq!(osd.message, Some("Paused".to_string()));
+        osd.tick(start, 0);
+        assert_eq!(osd.message, None);
+    }
+
+    #[test]
+    fn rates_are_per_second() {
+        let start = Instant::now();
+        let mut osd = Osd::new(start);
+        for frame in 1..=120 {
+            osd.tick(start + Duration::from_millis(frame * 1000 / 60), 10);
+        }
+        assert_eq!(osd.stats(), "60 FPS 600 IPS");
+    }
+}
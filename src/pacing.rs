@@ -0,0 +1,132 @@
+// Emulated seconds per real second, while fast forward is held
+const FAST_FORWARD_SPEED: f32 = 4.0;
+const SLOW_MOTION_SPEED: f32 = 0.25;
+
+// How many emulated frames to run for each 60hz frame on the host,
+// which is how pausing, single stepping and the speed controls work
+#[derive(Default)]
+pub struct Pacing {
+    paused: bool,
+    fast_forward: bool,
+    slow_motion: bool,
+    // Frames owed to the emulator, when running slower than one per host frame
+    owed: f32,
+    // Set by advance(), to run one frame while paused
+    step: bool
+}
+
+impl Pacing {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Emulated seconds per real second, ignoring pauses
+    pub fn speed(&self) -> f32 {
+        if self.fast_forward {
+            FAST_FORWARD_SPEED
+        } else if self.slow_motion {
+            SLOW_MOTION_SPEED
+        } else {
+            1.0
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.owed = 0.0;
+    }
+
+    // Runs exactly one frame, pausing first if need be
+    pub fn advance(&mut self) {
+        self.paused = true;
+        self.step = true;
+    }
+
+    pub fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+    }
+
+    pub fn toggle_slow_motion(&mut self) {
+        self.slow_motion = !self.slow_motion;
+    }
+
+    // Call once per host frame
+    pub fn frames_to_run(&mut self) -> u32 {
+        if self.paused {
+            let frames = if self.step { 1 } else { 0 };
+            self.step = false;
+            return frames;
+        }
+
+        self.owed += self.speed();
+        let frames = self.owed.floor();
+        self.owed -= frames;
+        frames as u32
+    }
+
+    // Shown to the user whenever it changes, None when running normally
+    pub fn status(&self) -> Option<String> {
+        if self.paused {
+            Some("paused".to_string())
+        } else if self.speed() != 1.0 {
+            Some(format!("speed {}x", self.speed()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod pacing_tests {
+    use super::*;
+
+    fn frames_over(pacing: &mut Pacing, host_frames: u32) -> u32 {
+        (0..host_frames).map(|_| pacing.frames_to_run()).sum()
+    }
+
+    #[test]
+    fn runs_one_frame_per_frame() {
+        let mut pacing = Pacing::default();
+        assert_eq!(frames_over(&mut pacing, 60), 60);
+        assert_eq!(pacing.status(), None);
+    }
+
+    #[test]
+    fn pause_and_advance_work() {
+        let mut pacing = Pacing::default();
+        pacing.toggle_pause();
+        assert_eq!(frames_over(&mut pacing, 60), 0);
+        assert_eq!(pacing.status(), Some("paused".to_string()));
+
+        pacing.advance();
+        assert_eq!(frames_over(&mut pacing, 60), 1);
+        assert!(pacing.is_paused());
+
+        pacing.toggle_pause();
+        assert_eq!(frames_over(&mut pacing, 60), 60);
+    }
+
+    #[test]
+    fn advance_pauses_a_running_game() {
+        let mut pacing = Pacing::default();
+        pacing.advance();
+        assert!(pacing.is_paused());
+        assert_eq!(frames_over(&mut pacing, 10), 1);
+    }
+
+    #[test]
+    fn speed_changes_work() {
+        let mut pacing = Pacing::default();
+        pacing.set_fast_forward(true);
+        assert_eq!(frames_over(&mut pacing, 60), 240);
+        assert_eq!(pacing.status(), Some("speed 4x".to_string()));
+
+        // fast forward wins while it's held
+        pacing.toggle_slow_motion();
+        assert_eq!(pacing.speed(), FAST_FORWARD_SPEED);
+
+        pacing.set_fast_forward(false);
+        assert_eq!(frames_over(&mut pacing, 60), 15);
+        assert_eq!(pacing.status(), Some("speed 0.25x".to_string()));
+    }
+}
@@ -0,0 +1,109 @@
+use sdl2::pixels::Color;
+
+// A pair of colours used to draw the CHIP-8 display
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    // Colour of lit pixels
+    pub foreground: Color,
+    // Colour of unlit pixels and the area around the display
+    pub background: Color
+}
+
+impl Palette {
+    pub fn new(name: &str, foreground: Color, background: Color) -> Palette {
+        Palette {
+            name: name.to_string(),
+            foreground,
+            background
+        }
+    }
+
+    // Mix between the background (0.0) and the foreground (1.0)
+    pub fn shade(&self, intensity: f32) -> Color {
+        let mix = |back: u8, fore: u8| {
+            (back as f32 + (fore as f32 - back as f32) * intensity).round() as u8
+        };
+
+        Color::RGB(
+            mix(self.background.r, self.foreground.r),
+            mix(self.background.g, self.foreground.g),
+            mix(self.background.b, self.foreground.b)
+        )
+    }
+}
+
+pub fn builtin_themes() -> Vec<Palette> {
+    vec![
+        // the original chip8.rs look
+        Palette::new("default", Color::RGB(255, 140, 0), Color::RGB(0, 0, 128)),
+        // P1 phosphor, like a terminal from the 70s
+        Palette::new("green", Color::RGB(51, 255, 51), Color::RGB(0, 20, 0)),
+        // P3 phosphor
+        Palette::new("amber", Color::RGB(255, 176, 0), Color::RGB(26, 15, 0)),
+        // dot matrix handheld LCD
+        Palette::new("lcd", Color::RGB(15, 56, 15), Color::RGB(155, 188, 15)),
+        Palette::new("high-contrast", Color::RGB(255, 255, 255), Color::RGB(0, 0, 0))
+    ]
+}
+
+// Position of the named theme in the list, ignoring case
+pub fn find_theme(themes: &[Palette], name: &str) -> Option<usize> {
+    themes.iter().position(|theme| theme.name == name.to_lowercase())
+}
+
+// Parses colours written as RRGGBB, with or without a leading '#'
+pub fn parse_color(text: &str) -> Result<Color, String> {
+    let hex = text.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a colour, expected RRGGBB", text));
+    }
+
+    let channel = |offset: usize| u8::from_str_radix(&hex[offset..offset + 2], 16).unwrap();
+    Ok(Color::RGB(channel(0), channel(2), channel(4)))
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    #[test]
+    fn default_theme_comes_first() {
+        let themes = builtin_themes();
+        assert_eq!(themes[0].name, "default");
+        assert_eq!(themes[0].foreground, Color::RGB(255, 140, 0));
+        assert_eq!(themes[0].background, Color::RGB(0, 0, 128));
+    }
+
+    #[test]
+    fn find_theme_works() {
+        let themes = builtin_themes();
+        assert_eq!(find_theme(&themes, "default"), Some(0));
+        assert_eq!(themes[find_theme(&themes, "amber").unwrap()].name, "amber");
+        assert_eq!(themes[find_theme(&themes, "LCD").unwrap()].name, "lcd"); // case insensitive
+        assert!(find_theme(&themes, "vaporwave").is_none());
+    }
+
+    #[test]
+    fn shade_mixes_colours() {
+        let palette = Palette::new("test", Color::RGB(200, 100, 0), Color::RGB(0, 0, 100));
+        assert_eq!(palette.shade(0.0), palette.background);
+        assert_eq!(palette.shade(1.0), palette.foreground);
+        assert_eq!(palette.shade(0.5), Color::RGB(100, 50, 50));
+    }
+
+    #[test]
+    fn parse_color_works() {
+        assert_eq!(parse_color("ff8c00"), Ok(Color::RGB(255, 140, 0)));
+        assert_eq!(parse_color("#000080"), Ok(Color::RGB(0, 0, 128)));
+        assert_eq!(parse_color("#AbCdEf"), Ok(Color::RGB(0xab, 0xcd, 0xef)));
+    }
+
+    #[test]
+    fn parse_color_rejects_garbage() {
+        assert!(parse_color("").is_err());
+        assert!(parse_color("#fff").is_err());
+        assert!(parse_color("orange").is_err());
+        assert!(parse_color("12345g").is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+// How long lit pixels linger on screen after the program turns them off.
+// CHIP-8 games erase and redraw their sprites with XOR every frame, which
+// flickers badly on a display that shows each frame exactly as it was drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PersistenceMode {
+    // Show the framebuffer as-is
+    Off,
+    // Average each pixel over the last N frames
+    Blend(usize),
+    // Lit pixels are drawn at full brightness, then fade by this factor every frame
+    Phosphor(f32)
+}
+
+impl PersistenceMode {
+    // Accepts "off", "blend", "blend:N", "phosphor" or "phosphor:DECAY"
+    pub fn parse(text: &str) -> Result<PersistenceMode, String> {
+        let mut parts = text.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let argument = parts.next();
+
+        match (name, argument) {
+            ("off", None) => Ok(PersistenceMode::Off),
+            ("blend", None) => Ok(PersistenceMode::Blend(3)),
+            ("blend", Some(frames)) => match frames.parse::<usize>() {
+                Ok(frames) if frames > 0 => Ok(PersistenceMode::Blend(frames)),
+                _ => Err(format!("'{}' is not a frame count", frames))
+            },
+            ("phosphor", None) => Ok(PersistenceMode::Phosphor(0.5)),
+            ("phosphor", Some(decay)) => match decay.parse::<f32>() {
+                Ok(decay) if (0.0..1.0).contains(&decay) => Ok(PersistenceMode::Phosphor(decay)),
+                _ => Err(format!("'{}' is not a decay factor between 0 and 1", decay))
+            },
+            _ => Err(format!("Unknown persistence mode '{}', try off, blend[:N] or phosphor[:DECAY]", text))
+        }
+    }
+
+    // The mode after this one when cycling with the hotkey
+    pub fn next(&self) -> PersistenceMode {
+        match *self {
+            PersistenceMode::Off => PersistenceMode::Blend(3),
+            PersistenceMode::Blend(_) => PersistenceMode::Phosphor(0.5),
+            PersistenceMode::Phosphor(_) => PersistenceMode::Off
+        }
+    }
+}
+
+impl fmt::Display for PersistenceMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PersistenceMode::Off => write!(f, "off"),
+            PersistenceMode::Blend(frames) => write!(f, "blend:{}", frames),
+            PersistenceMode::Phosphor(decay) => write!(f, "phosphor:{}", decay)
+        }
+    }
+}
+
+// Turns the on/off framebuffer into per-pixel brightness, once per frame
+pub struct Persistence {
+    mode: PersistenceMode,
+    // Most recent frame last, only used for blending
+    history: VecDeque<Vec<u8>>,
+    // 0.0 (background) to 1.0 (foreground) for every pixel
+    intensity: Vec<f32>
+}
+
+impl Persistence {
+    pub fn new(mode: PersistenceMode) -> Persistence {
+        Persistence {
+            mode,
+            history: VecDeque::new(),
+            intensity: Vec::new()
+        }
+    }
+
+    pub fn mode(&self) -> PersistenceMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: PersistenceMode) {
+        self.mode = mode;
+        self.history.clear();
+    }
+
+    pub fn update(&mut self, gfx: &[u8]) {
+        if self.intensity.len() != gfx.len() {
+            self.intensity = vec![0.0; gfx.len()];
+            self.history.clear();
+        }
+
+        match self.mode {
+            PersistenceMode::Off => {
+                for (out, pixel) in self.intensity.iter_mut().zip(gfx) {
+                    *out = if *pixel > 0 { 1.0 } else { 0.0 };
+                }
+            },
+            PersistenceMode::Blend(frames) => {
+                self.history.push_back(gfx.to_vec());
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+
+                let count = self.history.len() as f32;
+                for (i, out) in self.intensity.iter_mut().enumerate() {
+                    let lit = self.history.iter().filter(|frame| frame[i] > 0).count();
+                    *out = lit as f32 / count;
+                }
+            },
+            PersistenceMode::Phosphor(decay) => {
+                for (out, pixel) in self.intensity.iter_mut().zip(gfx) {
+                    *out = if *pixel > 0 { 1.0 } else { *out * decay };
+                }
+            }
+        }
+    }
+
+    pub fn intensity(&self) -> &[f32] {
+        &self.intensity
+    }
+}
+
+#[cfg(test)]
+mod persistence_tests {
+    use super::*;
+
+    #[test]
+    fn parse_works() {
+        assert_eq!(PersistenceMode::parse("off"), Ok(PersistenceMode::Off));
+        assert_eq!(PersistenceMode::parse("blend"), Ok(PersistenceMode::Blend(3)));
+        assert_eq!(PersistenceMode::parse("blend:5"), Ok(PersistenceMode::Blend(5)));
+        assert_eq!(PersistenceMode::parse("phosphor:0.25"), Ok(PersistenceMode::Phosphor(0.25)));
+
+        assert!(PersistenceMode::parse("blend:0").is_err());
+        assert!(PersistenceMode::parse("phosphor:2").is_err());
+        assert!(PersistenceMode::parse("off:3").is_err());
+        assert!(PersistenceMode::parse("smear").is_err());
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        for mode in &[PersistenceMode::Off, PersistenceMode::Blend(4), PersistenceMode::Phosphor(0.75)] {
+            assert_eq!(PersistenceMode::parse(&mode.to_string()), Ok(*mode));
+        }
+    }
+
+    #[test]
+    fn off_mirrors_the_framebuffer() {
+        let mut persistence = Persistence::new(PersistenceMode::Off);
+        persistence.update(&[1, 0, 1]);
+        persistence.update(&[0, 0, 1]);
+        assert_eq!(persistence.intensity(), &[0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn blend_averages_recent_frames() {
+        let mut persistence = Persistence::new(PersistenceMode::Blend(2));
+        persistence.update(&[1, 1]);
+        persistence.update(&[1, 0]);
+        assert_eq!(persistence.intensity(), &[1.0, 0.5]);
+
+        // the first frame has dropped out of the window now
+        persistence.update(&[0, 0]);
+        assert_eq!(persistence.intensity(), &[0.5, 0.0]);
+    }
+
+    #[test]
+    fn phosphor_fades_out() {
+        let mut persistence = Persistence::new(PersistenceMode::Phosphor(0.5));
+        persistence.update(&[1]);
+        assert_eq!(persistence.intensity(), &[1.0]);
+        persistence.update(&[0]);
+        assert_eq!(persistence.intensity(), &[0.5]);
+        persistence.update(&[0]);
+        assert_eq!(persistence.intensity(), &[0.25]);
+
+        // relighting is instant
+        persistence.update(&[1]);
+        assert_eq!(persistence.intensity(), &[1.0]);
+    }
+}
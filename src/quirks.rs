@@ -0,0 +1,39 @@
+// Behaviours that differ between CHIP-8 interpreters, which some
+// programs depend on
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quirks {
+    // Drawing a sprite waits for the next vertical blank, like the COSMAC VIP.
+    // Limits programs to one sprite per frame, but they no longer tear or flicker.
+    pub display_wait: bool,
+    // FX0A finishes as soon as a key goes down, instead of waiting for it to
+    // be released again like the COSMAC VIP. Some later interpreters did this.
+    pub key_wait_on_press: bool
+}
+
+// Names of the interpreters whose quirks can be picked all at once
+pub const PRESETS: [&str; 3] = ["vip", "schip", "modern"];
+
+impl Quirks {
+    pub fn preset(name: &str) -> Result<Quirks, String> {
+        match name {
+            "vip" => Ok(Quirks { display_wait: true, key_wait_on_press: false }),
+            "schip" => Ok(Quirks { display_wait: false, key_wait_on_press: false }),
+            "modern" => Ok(Quirks { display_wait: false, key_wait_on_press: true }),
+            _ => Err(format!("Unknown quirks preset '{}', try one of: {}", name, PRESETS.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod quirks_tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_found() {
+        for name in PRESETS.iter() {
+            assert!(Quirks::preset(name).is_ok());
+        }
+        assert!(Quirks::preset("vip").unwrap().display_wait);
+        assert!(Quirks::preset("chip-9").unwrap_err().contains("vip, schip, modern"));
+    }
+}
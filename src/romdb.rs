@@ -0,0 +1,260 @@
+use std::fmt;
+use quirks::Quirks;
+use sha1::sha1_hex;
+
+// What a program was written for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    // None of the bundled games need these yet
+    #[allow(dead_code)]
+    Schip,
+    #[allow(dead_code)]
+    XoChip
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::Schip => "SCHIP",
+            Platform::XoChip => "XO-CHIP"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// What some CHIP-8 keys do in a game
+#[derive(Debug, PartialEq)]
+pub struct KeyHint {
+    pub keys: &'static [u8],
+    pub action: &'static str
+}
+
+// What's known about a ROM. CHIP-8 programs have no header to say what
+// they are, so they're recognised by their contents.
+#[derive(Debug, PartialEq)]
+pub struct RomInfo {
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub author: &'static str,
+    // What to do, for the help
+    pub goal: &'static str,
+    pub platform: Platform,
+    // What it plays well at
+    pub instructions_per_frame: u32,
+    pub quirks: Quirks,
+    pub keys: &'static [KeyHint]
+}
+
+// Programs written for the COSMAC VIP, and those written later for
+// CHIP-48 on the HP48, which drew without waiting for the display
+const VIP: Quirks = Quirks { display_wait: true, key_wait_on_press: false };
+const CHIP48: Quirks = Quirks { display_wait: false, key_wait_on_press: false };
+
+const ALL_KEYS: [u8; 16] = [0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9, 0xa, 0xb, 0xc, 0xd, 0xe, 0xf];
+
+// The games that come in roms/c8games
+pub static ROMS: [RomInfo; 23] = [
+    RomInfo {
+        sha1: "ea9af3c09b0d9e265fcd92bcc5d51a2939fdf27a", title: "15 Puzzle", author: "Roger Ivie",
+        goal: "Slide the tiles back into order, from 1 to F.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: VIP,
+        keys: &[KeyHint { keys: &ALL_KEYS, action: "slide the tile in that square" }]
+    },
+    RomInfo {
+        sha1: "d40abc54374e4343639f993e897e00904ddf85d9", title: "Blinky", author: "Hans Christian Egeberg",
+        goal: "Eat every pill in the maze without getting caught by the ghosts. The big pills let you eat them for a while.",
+        platform: Platform::Chip8, instructions_per_frame: 15, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x3], action: "up" }, KeyHint { keys: &[0x6], action: "down" },
+            KeyHint { keys: &[0x7], action: "left" }, KeyHint { keys: &[0x8], action: "right" }
+        ]
+    },
+    RomInfo {
+        sha1: "6f6509f38220e057a7e32ebb22dd353c1078e3e7", title: "Blitz", author: "David Winter",
+        goal: "Bomb the city flat so your plane can land. It flies a little lower on every pass.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x5], action: "drop a bomb" }]
+    },
+    RomInfo {
+        sha1: "f13766c14aeb02ad8d4d103cb5eadd282d20cddc", title: "Brix", author: "Andreas Gustafsson",
+        goal: "Knock out every brick with the ball, and don't let it get past your paddle.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x4], action: "left" }, KeyHint { keys: &[0x6], action: "right" }]
+    },
+    RomInfo {
+        sha1: "2d10c07b532f4fa7c07a07324ba26ca39fe484fd", title: "Connect 4", author: "David Winter",
+        goal: "Two players take turns dropping counters. Get four in a row first.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x4], action: "left" }, KeyHint { keys: &[0x6], action: "right" },
+            KeyHint { keys: &[0x5], action: "drop a counter" }
+        ]
+    },
+    RomInfo {
+        sha1: "5260f8931e0e9f41e555b382a14a88368e3ed886", title: "Guess", author: "David Winter",
+        goal: "Think of a number from 1 to 63, and say whether it's on each card shown. The computer works out which it was.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x5], action: "on the card (other keys: no)" }]
+    },
+    RomInfo {
+        sha1: "050f07a54371da79f924dd0227b89d07b4f2aed0", title: "Hidden", author: "David Winter",
+        goal: "Turn the cards over two at a time to find all the matching pairs, in as few turns as you can.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x2, 0x8, 0x4, 0x6], action: "up, down, left, right" },
+            KeyHint { keys: &[0x5], action: "turn a card over" }
+        ]
+    },
+    RomInfo {
+        sha1: "f100197f0f2f05b4f3c8c31ab9c2c3930d3e9571", title: "Space Invaders", author: "David Winter",
+        goal: "Shoot down the invaders before they land.",
+        platform: Platform::Chip8, instructions_per_frame: 15, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x4], action: "left" }, KeyHint { keys: &[0x6], action: "right" },
+            KeyHint { keys: &[0x5], action: "fire, and start" }
+        ]
+    },
+    RomInfo {
+        sha1: "d6fa9dc9005dc0496f39ba52fef56f9fd0a5a158", title: "Kaleidoscope", author: "Joseph Weisbecker",
+        goal: "Draw a line and watch it mirrored into a kaleidoscope pattern.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: VIP,
+        keys: &[
+            KeyHint { keys: &[0x2, 0x8, 0x4, 0x6], action: "draw up, down, left, right" },
+            KeyHint { keys: &[0x0], action: "repeat the pattern" }
+        ]
+    },
+    RomInfo {
+        sha1: "b9272ae1acdaaa79ab649f6b48b72088ca2b1d74", title: "Maze", author: "David Winter",
+        goal: "Nothing to play: sit back and watch it draw a random maze.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[]
+    },
+    RomInfo {
+        sha1: "d979858bb9ffd07b48f52f92a8bcac0199f3623e", title: "Merlin", author: "David Winter",
+        goal: "Watch the squares light up, then repeat the sequence. It gets longer every round.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x1, 0x2, 0x4, 0x5], action: "the four squares, as laid out" }]
+    },
+    RomInfo {
+        sha1: "0d0cc129dad3c45ba672f85fec71a668232212cc", title: "Missile Command", author: "David Winter",
+        goal: "Hit all eight targets with your twelve missiles. The launcher speeds up with every shot.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x8], action: "fire" }]
+    },
+    RomInfo {
+        sha1: "b232ef880bd6060fb45fa6effed7edf0ae95670e", title: "Pong", author: "Paul Vervalin",
+        goal: "Two players. Keep the ball in play and get it past the other paddle.",
+        platform: Platform::Chip8, instructions_per_frame: 7, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x1, 0x4], action: "left paddle up, down" },
+            KeyHint { keys: &[0xc, 0xd], action: "right paddle up, down" }
+        ]
+    },
+    RomInfo {
+        sha1: "a60611339661e3ab2d8af024ad1da5880a6f8665", title: "Pong 2", author: "unknown",
+        goal: "Two players. Keep the ball in play and get it past the other paddle.",
+        platform: Platform::Chip8, instructions_per_frame: 7, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x1, 0x4], action: "left paddle up, down" },
+            KeyHint { keys: &[0xc, 0xd], action: "right paddle up, down" }
+        ]
+    },
+    RomInfo {
+        sha1: "1293db0ccccbe7dd3fc5a09a2abc5d7b175e18e0", title: "Puzzle", author: "unknown",
+        goal: "Slide the tiles back into order.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &ALL_KEYS, action: "slide the tile in that square" }]
+    },
+    RomInfo {
+        sha1: "1bdb4ddaa7049266fa3226851f28855a365cfd12", title: "Syzygy", author: "Roy Trevino",
+        goal: "Steer the snake to the numbers to eat them. It grows as it eats, so don't run into yourself.",
+        platform: Platform::Chip8, instructions_per_frame: 15, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x3], action: "up" }, KeyHint { keys: &[0x6], action: "down" },
+            KeyHint { keys: &[0x7], action: "left" }, KeyHint { keys: &[0x8], action: "right" },
+            KeyHint { keys: &[0xe, 0xf], action: "start without, with a border" }
+        ]
+    },
+    RomInfo {
+        sha1: "18b9d15f4c159e1f0ed58c2d8ec1d89325d3a3b6", title: "Tank", author: "unknown",
+        goal: "Drive your tank around the field and shoot the enemy.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x8, 0x2, 0x4, 0x6], action: "up, down, left, right" },
+            KeyHint { keys: &[0x5], action: "fire" }
+        ]
+    },
+    RomInfo {
+        sha1: "5f518084744bf3cb8733f6e5454dfd1634320563", title: "Tetris", author: "Fran Dachille",
+        goal: "Fit the falling blocks together into full rows, which disappear, before the pile reaches the top.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x5], action: "left" }, KeyHint { keys: &[0x6], action: "right" },
+            KeyHint { keys: &[0x4], action: "rotate" }, KeyHint { keys: &[0x7], action: "drop" }
+        ]
+    },
+    RomInfo {
+        sha1: "429d455a4bc53167942bf6fd934d72b0f648dce3", title: "Tic-Tac-Toe", author: "David Winter",
+        goal: "Two players take turns. Get three in a row first.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0x8, 0x9], action: "the nine squares" }]
+    },
+    RomInfo {
+        sha1: "bdb92475acfe11bc7814a2f5eade13fcd09b756a", title: "UFO", author: "Lutz V",
+        goal: "Shoot down the flying saucers going overhead before your missiles run out.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[KeyHint { keys: &[0x4, 0x5, 0x6], action: "fire left, straight up, right" }]
+    },
+    RomInfo {
+        sha1: "da710f631f8e35534d0b9170bcf892a60f49c43d", title: "Vertical Brix", author: "Paul Robson",
+        goal: "Knock out every brick with the ball, and don't let it get past your paddle.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x1, 0x4], action: "up, down" },
+            KeyHint { keys: &[0x7], action: "start" }
+        ]
+    },
+    RomInfo {
+        sha1: "ade839585ddeb0e3633177df03c1d91589e629eb", title: "Vers", author: "JMN",
+        goal: "Two players each steer a growing line. The first to crash into a wall or a line loses.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: CHIP48,
+        keys: &[
+            KeyHint { keys: &[0x1, 0x2, 0x7, 0xa], action: "steer the left player" },
+            KeyHint { keys: &[0xb, 0xc, 0xd, 0xf], action: "steer the right player" }
+        ]
+    },
+    RomInfo {
+        sha1: "d666688a8fce468a7d88b536bc1ef5f35ba12031", title: "Wipe Off", author: "Joseph Weisbecker",
+        goal: "Wipe every dot off the screen with the ball, and don't let it get past your paddle.",
+        platform: Platform::Chip8, instructions_per_frame: 10, quirks: VIP,
+        keys: &[KeyHint { keys: &[0x4], action: "left" }, KeyHint { keys: &[0x6], action: "right" }]
+    }
+];
+
+// Looks a ROM up by the SHA-1 of its contents
+pub fn find(hash: &str) -> Option<&'static RomInfo> {
+    ROMS.iter().find(|rom| rom.sha1 == hash)
+}
+
+pub fn identify(program: &[u8]) -> Option<&'static RomInfo> {
+    find(&sha1_hex(program))
+}
+
+#[cfg(test)]
+mod romdb_tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn the_bundled_games_are_known() {
+        for entry in fs::read_dir("roms/c8games").unwrap() {
+            let path = entry.unwrap().path();
+            let rom = identify(&fs::read(&path).unwrap());
+            assert!(rom.is_some(), "{}", path.display());
+        }
+        assert_eq!(identify(&fs::read("roms/c8games/BLINKY").unwrap()).unwrap().title, "Blinky");
+        assert!(identify(&[0x12, 0x00]).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+use ComputerState;
+use memory::StackDepth;
+
+// Marks a save state file, followed by a version byte
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+// Reads a save state back, field by field
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.position + count > self.bytes.len() {
+            return Err("save state is cut short".to_string());
+        }
+        let slice = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+}
+
+// A snapshot of the whole machine, to carry on from later. Quirks aren't
+// part of it, as they belong to the interpreter rather than the program,
+// and neither are the keys, which belong to the player.
+impl ComputerState {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+
+        bytes.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.program_counter.to_le_bytes());
+        bytes.extend_from_slice(&self.gfx);
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+
+        // an unlimited stack can go well past 255
+        bytes.extend_from_slice(&(self.stack.len() as u32).to_le_bytes());
+        for address in self.stack.iter() {
+            bytes.extend_from_slice(&address.to_le_bytes());
+        }
+
+        bytes.push(self.waiting_for_keypress as u8);
+        bytes.push(self.waiting_for_keypress_target as u8);
+        // 0xff for not waiting, as keys only go up to 0xf
+        bytes.push(self.waiting_for_key_release.unwrap_or(0xff));
+        bytes.push(self.waiting_for_vblank as u8);
+        bytes
+    }
+
+    // Leaves the machine untouched if the state can't be read
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(format!("save state version {} isn't supported", version));
+        }
+
+        let memory_size = reader.u32()? as usize;
+        if memory_size != self.memory.len() {
+            return Err(format!("save state has {} bytes of memory, expected {}", memory_size, self.memory.len()));
+        }
+
+        // the game, its font and its random numbers carry on as they were, and any
+        // fault is left behind with the moment it happened in
+        let mut state = ComputerState {
+            quirks: self.quirks.clone(),
+            keys: self.keys,
+            rng: self.rng.clone(),
+            rom: self.rom,
+            font_size: self.font_size,
+            ..ComputerState::with_layout(self.layout.clone())
+        };
+        state.memory.copy_from_slice(reader.take(memory_size)?);
+        state.registers.copy_from_slice(reader.take(16)?);
+        state.index = reader.u16()?;
+        state.program_counter = reader.u16()?;
+        let gfx_size = state.gfx.len();
+        state.gfx.copy_from_slice(reader.take(gfx_size)?);
+        state.delay_timer = reader.u8()?;
+        state.sound_timer = reader.u8()?;
+
+        let depth = reader.u32()? as usize;
+        if let StackDepth::Limit(limit) = self.layout.stack_depth {
+            if depth > limit {
+                return Err(format!("save state has {} calls on the stack, more than the {} there's room for", depth, limit));
+            }
+        }
+        for _ in 0..depth {
+            state.stack.push(reader.u16()?);
+        }
+
+        state.waiting_for_keypress = reader.bool()?;
+        state.waiting_for_keypress_target = match reader.u8()? {
+            target if target <= 0xf => target as usize,
+            target => return Err(format!("save state waits for a key in V{:X}, which doesn't exist", target))
+        };
+        state.waiting_for_key_release = match reader.u8()? {
+            0xff => None,
+            key if key <= 0xf => Some(key),
+            key => return Err(format!("save state waits for key {:X}, which doesn't exist", key))
+        };
+        state.waiting_for_vblank = reader.bool()?;
+
+        // the host should start the beeper up again if it was going
+        state.sound_timer_write = Some(state.sound_timer);
+
+        *self = state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod savestate_tests {
+    use ComputerState;
+    use memory::{MemoryLayout, StackDepth};
+    use std::fs;
+
+    #[test]
+    fn states_round_trip() {
+        let mut computer = ComputerState::new();
+        computer.memory[0x300] = 0xab;
+        computer.registers[5] = 42;
+        computer.index = 0x123;
+        computer.program_counter = 0x2f0;
+        computer.gfx[100] = 1;
+        computer.delay_timer = 7;
+        computer.sound_timer = 9;
+        computer.stack.push(0x204);
+        computer.waiting_for_key_release = Some(0xc);
+
+        let mut restored = ComputerState::new();
+        restored.load_state(&computer.save_state()).unwrap();
+        assert_eq!(restored.save_state(), computer.save_state());
+        assert_eq!(restored.program_counter, 0x2f0);
+        assert_eq!(restored.stack, vec![0x204]);
+        assert_eq!(restored.take_sound_timer_write(), Some(9));
+    }
+
+    #[test]
+    fn loading_keeps_the_game() {
+        let mut computer = ComputerState::new();
+        computer.load_bytes(&fs::read("roms/c8games/PONG").unwrap()).unwrap();
+        let title = computer.rom.map(|rom| rom.title);
+        assert!(title.is_some());
+
+        let state = computer.save_state();
+        computer.step();
+        computer.load_state(&state).unwrap();
+        assert_eq!(computer.rom.map(|rom| rom.title), title);
+        assert_eq!(computer.program_counter, 0x200);
+    }
+
+    #[test]
+    fn bad_states_are_refused() {
+        let mut computer = ComputerState::new();
+        computer.registers[0] = 1;
+
+        assert!(computer.load_state(b"not a state").is_err());
+
+        let mut state = ComputerState::new().save_state();
+        state.truncate(state.len() - 10);
+        assert!(computer.load_state(&state).unwrap_err().contains("cut short"));
+
+        // a register past VF to put the key in
+        let mut state = ComputerState::new().save_state();
+        let target = state.len() - 3;
+        state[target] = 0x10;
+        assert!(computer.load_state(&state).unwrap_err().contains("V10"));
+
+        // untouched by the failed loads
+        assert_eq!(computer.registers[0], 1);
+    }
+
+    #[test]
+    fn deep_stacks_are_kept_whole() {
+        let unlimited = MemoryLayout { stack_depth: StackDepth::Unlimited, ..MemoryLayout::default() };
+        let mut computer = ComputerState::with_layout(unlimited.clone());
+        for depth in 0..300 {
+            computer.stack.push(0x200 + depth * 2);
+        }
+
+        let mut restored = ComputerState::with_layout(unlimited);
+        restored.load_state(&computer.save_state()).unwrap();
+        assert_eq!(restored.stack, computer.stack);
+
+        // more than a limited stack could have got to
+        let mut limited = ComputerState::new();
+        assert!(limited.load_state(&computer.save_state()).unwrap_err().contains("300 calls"));
+    }
+}
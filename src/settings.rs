@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use audio::{Tone, Waveform};
+use quirks::Quirks;
+use romdb;
+
+// The choices made in the pause menu, kept between runs. Options on
+// the command line win over these.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub instructions_per_frame: u32,
+    // Name of a theme
+    pub palette: String,
+    pub quirks: Quirks,
+    // Name of a key preset, or the path to a key mapping file
+    pub keys: String,
+    // Name of a built-in font, or the path to a font file
+    pub font: String,
+    // 0.0 to 1.0
+    pub volume: f32,
+    // Pitch of the beeper in Hz
+    pub tone: f32,
+    pub waveform: Waveform,
+    // Where the ROM browser looks
+    pub roms: String,
+    // What's different for particular games, by the SHA-1 of the ROM
+    pub games: BTreeMap<String, GameSettings>
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        let tone = Tone::default();
+        Settings {
+            instructions_per_frame: 1,
+            palette: "default".to_string(),
+            quirks: Quirks::default(),
+            keys: "qwerty".to_string(),
+            font: "chip48".to_string(),
+            volume: tone.volume,
+            tone: tone.frequency,
+            waveform: tone.waveform,
+            roms: "roms/c8games".to_string(),
+            games: BTreeMap::new()
+        }
+    }
+}
+
+// A game's own section of the settings file. Anything it leaves out
+// comes from the defaults.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GameSettings {
+    // Only there to make the file easier to read
+    pub name: Option<String>,
+    pub instructions_per_frame: Option<u32>,
+    pub palette: Option<String>,
+    pub display_wait: Option<bool>,
+    pub key_wait_on_press: Option<bool>,
+    pub keys: Option<String>,
+    pub font: Option<String>,
+    pub volume: Option<f32>,
+    pub tone: Option<f32>,
+    pub waveform: Option<Waveform>
+}
+
+impl GameSettings {
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "name" => self.name = Some(value.to_string()),
+            "ipf" => self.instructions_per_frame = match value.parse::<u32>() {
+                Ok(count) if count > 0 => Some(count),
+                _ => return Err(format!("'{}' is not a number of instructions per frame", value))
+            },
+            "palette" => self.palette = Some(value.to_string()),
+            "display_wait" => self.display_wait = Some(parse_switch(value)?),
+            "key_wait" => self.key_wait_on_press = match value {
+                "press" => Some(true),
+                "release" => Some(false),
+                _ => return Err(format!("key_wait is 'press' or 'release', not '{}'", value))
+            },
+            "keys" => self.keys = Some(value.to_string()),
+            "font" => self.font = Some(value.to_string()),
+            "volume" => self.volume = match value.parse::<f32>() {
+                Ok(volume) if (0.0..=1.0).contains(&volume) => Some(volume),
+                _ => return Err(format!("'{}' is not a volume between 0 and 1", value))
+            },
+            "tone" => self.tone = match value.parse::<f32>() {
+                Ok(frequency) if frequency > 0.0 && frequency < 20_000.0 => Some(frequency),
+                _ => return Err(format!("'{}' is not a frequency in Hz", value))
+            },
+            "waveform" => self.waveform = Some(Waveform::parse(value)?),
+            _ => return Err(format!("unknown setting '{}'", name))
+        }
+        Ok(())
+    }
+
+    // Whatever differs in `current` from `base`
+    fn between(base: &Settings, current: &Settings) -> GameSettings {
+        GameSettings {
+            name: None,
+            instructions_per_frame: changed(&base.instructions_per_frame, &current.instructions_per_frame),
+            palette: changed(&base.palette, &current.palette),
+            display_wait: changed(&base.quirks.display_wait, &current.quirks.display_wait),
+            key_wait_on_press: changed(&base.quirks.key_wait_on_press, &current.quirks.key_wait_on_press),
+            keys: changed(&base.keys, &current.keys),
+            font: changed(&base.font, &current.font),
+            volume: changed(&base.volume, &current.volume),
+            tone: changed(&base.tone, &current.tone),
+            waveform: changed(&base.waveform, &current.waveform)
+        }
+    }
+
+    fn apply(&self, settings: &mut Settings) {
+        if let Some(count) = self.instructions_per_frame {
+            settings.instructions_per_frame = count;
+        }
+        if let Some(ref palette) = self.palette {
+            settings.palette = palette.clone();
+        }
+        if let Some(display_wait) = self.display_wait {
+            settings.quirks.display_wait = display_wait;
+        }
+        if let Some(key_wait_on_press) = self.key_wait_on_press {
+            settings.quirks.key_wait_on_press = key_wait_on_press;
+        }
+        if let Some(ref keys) = self.keys {
+            settings.keys = keys.clone();
+        }
+        if let Some(ref font) = self.font {
+            settings.font = font.clone();
+        }
+        if let Some(volume) = self.volume {
+            settings.volume = volume;
+        }
+        if let Some(tone) = self.tone {
+            settings.tone = tone;
+        }
+        if let Some(waveform) = self.waveform {
+            settings.waveform = waveform;
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(ref name) = self.name {
+            text += &format!("name = {}\n", name);
+        }
+        if let Some(count) = self.instructions_per_frame {
+            text += &format!("ipf = {}\n", count);
+        }
+        if let Some(ref palette) = self.palette {
+            text += &format!("palette = {}\n", palette);
+        }
+        if let Some(display_wait) = self.display_wait {
+            text += &format!("display_wait = {}\n", if display_wait { "on" } else { "off" });
+        }
+        if let Some(key_wait_on_press) = self.key_wait_on_press {
+            text += &format!("key_wait = {}\n", if key_wait_on_press { "press" } else { "release" });
+        }
+        if let Some(ref keys) = self.keys {
+            text += &format!("keys = {}\n", keys);
+        }
+        if let Some(ref font) = self.font {
+            text += &format!("font = {}\n", font);
+        }
+        if let Some(volume) = self.volume {
+            text += &format!("volume = {}\n", volume);
+        }
+        if let Some(tone) = self.tone {
+            text += &format!("tone = {}\n", tone);
+        }
+        if let Some(waveform) = self.waveform {
+            text += &format!("waveform = {}\n", waveform);
+        }
+        text
+    }
+}
+
+// Only what's different from the default
+fn changed<T: Clone + PartialEq>(default: &T, current: &T) -> Option<T> {
+    if current != default { Some(current.clone()) } else { None }
+}
+
+impl Settings {
+    // Settings files look like:
+    //
+    //   ipf = 10
+    //   palette = green
+    //   display_wait = on
+    //   key_wait = press
+    //   keys = qwerty
+    //   font = chip48
+    //   volume = 0.5
+    //   tone = 440
+    //   waveform = sine
+    //   roms = ~/chip8/games
+    //
+    //   [d40abc54374e4343639f993e897e00904ddf85d9]
+    //   name = BLINKY
+    //   ipf = 15
+    //   keys = keys/blinky.txt
+    //
+    // with a section for each game that needs something different, headed
+    // by the SHA-1 of the ROM. Anything left out keeps its default.
+    pub fn parse(text: &str) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        let mut defaults = GameSettings::default();
+        let mut section: Option<String> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let result = if line.starts_with('[') && line.ends_with(']') {
+                let hash = line[1..line.len() - 1].trim().to_lowercase();
+                if hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    settings.games.entry(hash.clone()).or_default();
+                    section = Some(hash);
+                    Ok(())
+                } else {
+                    Err(format!("'{}' is not the SHA-1 of a ROM", hash))
+                }
+            } else {
+                let mut sides = line.splitn(2, '=');
+                let name = sides.next().unwrap().trim();
+                match (sides.next().map(|value| value.trim()), section.as_ref()) {
+                    (None, _) => Err(format!("expected 'name = value', got '{}'", line)),
+                    (Some(value), None) if name == "roms" => {
+                        settings.roms = value.to_string();
+                        Ok(())
+                    },
+                    (Some(_), None) if name == "name" => Err("only games have names".to_string()),
+                    (Some(value), None) => defaults.set(name, value),
+                    (Some(value), Some(hash)) => settings.games.get_mut(hash).unwrap().set(name, value)
+                }
+            };
+            result.map_err(|message| format!("line {}: {}", number + 1, message))?;
+        }
+
+        defaults.apply(&mut settings);
+        Ok(settings)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# chip8.rs settings, written by the pause menu\n");
+        text += &format!("ipf = {}\n", self.instructions_per_frame);
+        text += &format!("palette = {}\n", self.palette);
+        text += &format!("display_wait = {}\n", if self.quirks.display_wait { "on" } else { "off" });
+        text += &format!("key_wait = {}\n", if self.quirks.key_wait_on_press { "press" } else { "release" });
+        text += &format!("keys = {}\n", self.keys);
+        text += &format!("font = {}\n", self.font);
+        text += &format!("volume = {}\n", self.volume);
+        text += &format!("tone = {}\n", self.tone);
+        text += &format!("waveform = {}\n", self.waveform);
+        text += &format!("roms = {}\n", self.roms);
+        for (hash, game) in self.games.iter() {
+            text += &format!("\n[{}]\n", hash);
+            text += &game.to_text();
+        }
+        text
+    }
+
+    pub fn tone(&self) -> Tone {
+        Tone { frequency: self.tone, waveform: self.waveform, volume: self.volume }
+    }
+
+    // The defaults, with the speed and quirks the ROM database has for
+    // the game
+    fn recommended(&self, hash: &str) -> Settings {
+        let mut settings = self.clone();
+        if let Some(rom) = romdb::find(hash) {
+            settings.instructions_per_frame = rom.instructions_per_frame;
+            settings.quirks = rom.quirks.clone();
+        }
+        settings
+    }
+
+    // The settings to play a game with, given the SHA-1 of its ROM
+    pub fn for_game(&self, hash: &str) -> Settings {
+        let mut settings = self.recommended(hash);
+        if let Some(game) = self.games.get(hash) {
+            game.apply(&mut settings);
+        }
+        settings
+    }
+
+    pub fn has_game(&self, hash: &str) -> bool {
+        self.games.contains_key(hash)
+    }
+
+    // Gives a game a section with whatever differs in `current` from
+    // the defaults and what the ROM database recommends, replacing any
+    // it had
+    pub fn remember_game(&mut self, hash: &str, name: &str, current: &Settings) {
+        let game = GameSettings {
+            name: Some(name.to_string()),
+            ..GameSettings::between(&self.recommended(hash), current)
+        };
+        self.games.insert(hash.to_string(), game);
+    }
+
+    // Gives a game's section whatever changed between `before` and
+    // `after`, keeping the rest of it. Anything that came from the
+    // command line is in both, so only lasts for the run.
+    pub fn change_game(&mut self, hash: &str, name: &str, before: &Settings, after: &Settings) {
+        let mut game = self.for_game(hash);
+        GameSettings::between(before, after).apply(&mut game);
+        self.remember_game(hash, name, &game);
+    }
+
+    // Makes whatever changed between `before` and `after` the default,
+    // leaving everything else, and the games' sections, alone
+    pub fn change_defaults(&mut self, before: &Settings, after: &Settings) {
+        GameSettings::between(before, after).apply(self);
+    }
+
+    // Not having saved any settings yet is fine
+    pub fn load(path: &PathBuf) -> Result<Settings, String> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Settings::default()),
+            Err(e) => return Err(format!("Could not open '{}': {}", path.display(), e))
+        };
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+        Settings::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        File::create(path)?.write_all(self.to_text().as_bytes())
+    }
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected 'on' or 'off', got '{}'", value))
+    }
+}
+
+// Where the settings live: the usual per-user config directory
+pub fn settings_path() -> PathBuf {
+    let dir = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from("."));
+    dir.join("chip8rs").join("settings.conf")
+}
+
+#[cfg(test)]
+mod settings_tests {
+    use super::*;
+
+    const BLINKY: &str = "d40abc54374e4343639f993e897e00904ddf85d9";
+
+    #[test]
+    fn parse_works() {
+        let text = "ipf = 10\npalette = green\ndisplay_wait = on\nkey_wait = press\nkeys = hex\nfont = vip\nvolume = 0.5\nwaveform = sine\n";
+        let settings = Settings::parse(text).unwrap();
+        assert_eq!(settings.instructions_per_frame, 10);
+        assert_eq!(settings.palette, "green");
+        assert!(settings.quirks.display_wait);
+        assert!(settings.quirks.key_wait_on_press);
+        assert_eq!(settings.keys, "hex");
+        assert_eq!(settings.font, "vip");
+        assert_eq!(settings.volume, 0.5);
+        assert_eq!(settings.waveform, Waveform::Sine);
+    }
+
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        let settings = Settings::parse("# nothing yet\npalette = amber").unwrap();
+        assert_eq!(settings, Settings { palette: "amber".to_string(), ..Settings::default() });
+    }
+
+    #[test]
+    fn saved_settings_read_back() {
+        let mut settings = Settings {
+            instructions_per_frame: 15,
+            palette: "lcd".to_string(),
+            quirks: Quirks { display_wait: true, key_wait_on_press: false },
+            keys: "keys/blinky.txt".to_string(),
+            font: "fonts/round.bin".to_string(),
+            volume: 0.75,
+            tone: 440.0,
+            waveform: Waveform::Triangle,
+            roms: "/home/me/chip8".to_string(),
+            games: BTreeMap::new()
+        };
+        settings.games.insert(BLINKY.to_string(), GameSettings {
+            name: Some("BLINKY".to_string()),
+            instructions_per_frame: Some(20),
+            key_wait_on_press: Some(true),
+            font: Some("schip".to_string()),
+            ..GameSettings::default()
+        });
+        assert_eq!(Settings::parse(&settings.to_text()), Ok(settings));
+    }
+
+    #[test]
+    fn parse_reports_bad_lines() {
+        assert!(Settings::parse("ipf = 0").unwrap_err().contains("line 1"));
+        assert!(Settings::parse("\nvolume = 11").unwrap_err().contains("line 2"));
+        assert!(Settings::parse("display_wait = maybe").is_err());
+        assert!(Settings::parse("colour = red").is_err());
+        assert!(Settings::parse("[BLINKY]\nipf = 15").unwrap_err().contains("not the SHA-1"));
+        assert!(Settings::parse("name = everything").is_err());
+    }
+
+    #[test]
+    fn games_have_their_own_settings() {
+        let text = format!("ipf = 10\nkeys = hex\n\n[{}]\nname = BLINKY\nipf = 15\nkeys = keys/blinky.txt\n", BLINKY.to_uppercase());
+        let settings = Settings::parse(&text).unwrap();
+        assert_eq!(settings.instructions_per_frame, 10);
+
+        let blinky = settings.for_game(BLINKY);
+        assert_eq!(blinky.instructions_per_frame, 15);
+        assert_eq!(blinky.keys, "keys/blinky.txt");
+        assert_eq!(blinky.palette, "default");
+        assert_eq!(settings.for_game("0000000000000000000000000000000000000000"), settings);
+    }
+
+    #[test]
+    fn games_remember_what_is_different() {
+        let mut settings = Settings::default();
+        let current = Settings { instructions_per_frame: 20, keys: "hex".to_string(), ..Settings::default() };
+        settings.remember_game(BLINKY, "BLINKY", &current);
+        assert!(settings.has_game(BLINKY));
+        assert_eq!(settings.games[BLINKY], GameSettings {
+            name: Some("BLINKY".to_string()),
+            instructions_per_frame: Some(20),
+            keys: Some("hex".to_string()),
+            ..GameSettings::default()
+        });
+
+        // and new defaults leave them be
+        let before = settings.for_game(BLINKY);
+        settings.change_defaults(&before, &Settings { volume: 1.0, ..before.clone() });
+        assert_eq!(settings.volume, 1.0);
+        assert_eq!(settings.instructions_per_frame, 1);
+        assert_eq!(settings.for_game(BLINKY).instructions_per_frame, 20);
+    }
+
+    #[test]
+    fn game_changes_leave_the_rest_alone() {
+        let mut settings = Settings::default();
+        settings.remember_game(BLINKY, "BLINKY", &Settings { instructions_per_frame: 20, ..settings.for_game(BLINKY) });
+
+        // as if run with --keys hex, then the palette changed in the menu
+        let before = Settings { keys: "hex".to_string(), ..settings.for_game(BLINKY) };
+        settings.change_game(BLINKY, "BLINKY", &before, &Settings { palette: "amber".to_string(), ..before.clone() });
+        assert_eq!(settings.games[BLINKY], GameSettings {
+            name: Some("BLINKY".to_string()),
+            instructions_per_frame: Some(20),
+            palette: Some("amber".to_string()),
+            ..GameSettings::default()
+        });
+    }
+
+    #[test]
+    fn known_games_start_from_the_rom_database() {
+        let mut settings = Settings::default();
+        let blinky = settings.for_game(BLINKY);
+        assert_eq!(blinky.instructions_per_frame, romdb::find(BLINKY).unwrap().instructions_per_frame);
+
+        // only what's different from the recommendation is kept
+        settings.remember_game(BLINKY, "BLINKY", &Settings { palette: "green".to_string(), ..blinky });
+        assert_eq!(settings.games[BLINKY].instructions_per_frame, None);
+        assert_eq!(settings.games[BLINKY].palette, Some("green".to_string()));
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// How often to look at the file, in frames
+const CHECK_FRAMES: u32 = 15;
+
+// What happens to the running game when its ROM changes on disk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchMode {
+    // a fresh machine, nothing kept
+    Reset,
+    // the new program from the load address, on the same display and registers
+    Restart
+}
+
+impl WatchMode {
+    pub fn parse(name: &str) -> Result<WatchMode, String> {
+        match name {
+            "reset" => Ok(WatchMode::Reset),
+            "restart" => Ok(WatchMode::Restart),
+            _ => Err(format!("Unknown watch mode '{}', try reset or restart", name))
+        }
+    }
+}
+
+impl fmt::Display for WatchMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            WatchMode::Reset => "reset",
+            WatchMode::Restart => "restart"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Notices when a file is changed, e.g. by rebuilding a ROM
+pub struct FileWatcher {
+    path: PathBuf,
+    // When the file was last changed, as of the last time it was loaded
+    modified: Option<SystemTime>,
+    // A newer change, waiting to settle so half-written files are skipped
+    pending: Option<SystemTime>,
+    frames: u32
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> FileWatcher {
+        FileWatcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            pending: None,
+            frames: 0
+        }
+    }
+
+    // Call once per frame. True once the file has changed and then
+    // stayed the same for a check, so it's ready to load again.
+    pub fn changed(&mut self) -> bool {
+        self.frames += 1;
+        if self.frames < CHECK_FRAMES {
+            return false;
+        }
+        self.frames = 0;
+
+        // a missing file is most likely being rebuilt, so wait for it
+        let current = match modified(&self.path) {
+            Some(time) => time,
+            None => return false
+        };
+        if Some(current) == self.modified {
+            self.pending = None;
+            return false;
+        }
+        if self.pending == Some(current) {
+            self.modified = Some(current);
+            self.pending = None;
+            return true;
+        }
+        self.pending = Some(current);
+        false
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn check(watcher: &mut FileWatcher) -> bool {
+        (0..CHECK_FRAMES).fold(false, |changed, _| watcher.changed() || changed)
+    }
+
+    #[test]
+    fn modes_parse() {
+        assert_eq!(WatchMode::parse("reset"), Ok(WatchMode::Reset));
+        assert_eq!(WatchMode::parse("restart"), Ok(WatchMode::Restart));
+        assert!(WatchMode::parse("reload").is_err());
+        assert_eq!(WatchMode::Restart.to_string(), "restart");
+    }
+
+    #[test]
+    fn changes_are_noticed_once_settled() {
+        let path = std::env::temp_dir().join("chip8_watch_test.ch8");
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!check(&mut watcher));
+
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert!(!check(&mut watcher));
+        assert!(check(&mut watcher));
+        assert!(!check(&mut watcher));
+
+        // gone while it's rebuilt, then back
+        fs::remove_file(&path).unwrap();
+        assert!(!check(&mut watcher));
+        fs::write(&path, [0x12, 0x02]).unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(later + Duration::from_secs(10)).unwrap();
+        assert!(!check(&mut watcher));
+        assert!(check(&mut watcher));
+        fs::remove_file(&path).unwrap();
+    }
+}
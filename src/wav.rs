@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+use audio::{Beeper, Tone};
+
+// Streams 16-bit mono PCM to a .wav file. The sizes in the header
+// aren't known until the end, so they're filled in by finish().
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    samples_written: u32
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        out.write_all(b"RIFF")?;
+        out.write_all(&[0u8; 4])?; // file size, filled in later
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?; // PCM
+        out.write_all(&1u16.to_le_bytes())?; // mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * 2).to_le_bytes())?; // bytes per second
+        out.write_all(&2u16.to_le_bytes())?; // bytes per sample
+        out.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+        out.write_all(b"data")?;
+        out.write_all(&[0u8; 4])?; // data size, filled in later
+
+        Ok(WavWriter { out, samples_written: 0 })
+    }
+
+    // -1.0 to 1.0, anything outside is clipped
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        self.out.write_all(&value.to_le_bytes())?;
+        self.samples_written += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = self.samples_written * 2;
+        // anything still buffered has to land before we go back for the header
+        self.out.flush()?;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Renders what the beeper would play, one emulated frame at a time, with
+// its own Beeper so it needs no audio device and never drifts from the emulator
+pub struct AudioRecorder {
+    beeper: Beeper,
+    writer: WavWriter<BufWriter<File>>,
+    sample_rate: u32,
+    // Samples owed from earlier frames, when the rate doesn't divide by 60
+    fraction: u32
+}
+
+impl AudioRecorder {
+    pub fn create(path: &str, sample_rate: u32) -> io::Result<AudioRecorder> {
+        let writer = WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)?;
+        Ok(AudioRecorder {
+            beeper: Beeper::new(Tone::default(), sample_rate as i32),
+            writer,
+            sample_rate,
+            fraction: 0
+        })
+    }
+
+    // Call once per emulated frame, with the tone as it is now and anything
+    // written to the sound timer during it
+    pub fn frame(&mut self, tone: &Tone, sound_timer_write: Option<u8>) -> io::Result<()> {
+        if self.beeper.tone() != tone {
+            self.beeper.set_tone(tone.clone());
+        }
+        if let Some(ticks) = sound_timer_write {
+            // recordings are in emulated time, whatever speed we're running at
+            self.beeper.play_ticks(ticks, 1.0);
+        }
+
+        self.fraction += self.sample_rate;
+        let samples = self.fraction / 60;
+        self.fraction %= 60;
+
+        for _ in 0..samples {
+            let sample = self.beeper.next_sample();
+            self.writer.write_sample(sample)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finish().map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod wav_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+    }
+
+    #[test]
+    fn header_is_right() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::<u8>::new()), 8000).unwrap();
+        writer.write_sample(0.0).unwrap();
+        writer.write_sample(1.0).unwrap();
+        writer.write_sample(-2.0).unwrap(); // clipped
+        let bytes = writer.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 6);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 24), 8000);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 6);
+
+        assert_eq!(&bytes[44..46], &[0, 0]);
+        assert_eq!(&bytes[46..48], &i16::MAX.to_le_bytes());
+        assert_eq!(&bytes[48..50], &(-i16::MAX).to_le_bytes());
+    }
+
+    #[test]
+    fn recorder_keeps_in_step_with_frames() {
+        let path = std::env::temp_dir().join("chip8_recorder_test.wav");
+        let path = path.to_str().unwrap();
+
+        // 22050 doesn't divide evenly by 60, but a second's worth of frames should
+        let tone = Tone::default();
+        let mut recorder = AudioRecorder::create(path, 22_050).unwrap();
+        recorder.frame(&tone, Some(30)).unwrap();
+        for _ in 1..60 {
+            recorder.frame(&tone, None).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let length = std::fs::metadata(path).unwrap().len();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(length, 44 + 22_050 * 2);
+    }
+}